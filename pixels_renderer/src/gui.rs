@@ -3,6 +3,7 @@ use egui_wgpu::renderer::{RenderPass, ScreenDescriptor};
 use env_logger::TimestampPrecision::Seconds;
 use pixels::{wgpu, PixelsContext};
 use simulation::entities::material::Material;
+use simulation::stats::TickStats;
 use std::time::{Duration, SystemTime};
 use winit::event_loop::EventLoopWindowTarget;
 use winit::window::Window;
@@ -24,12 +25,25 @@ pub(crate) struct Framework {
 /// Example application state. A real application will need a lot more state than this.
 pub struct Gui {
     pub material: Material,
+    /// The materials to choose from, with their names.
+    pub materials: Vec<(Material, String)>,
+    /// The thickness of brush strokes in cells.
+    pub brush_size: u8,
     pub tick_interval: u8,
     pub tick_duration: Duration,
     pub frame_duration: Duration,
     pub last_frame_time: SystemTime,
-    pub failed_locks: usize,
     pub num_threads: usize,
+    /// Whether the undo button has been clicked since the last frame.
+    pub undo_requested: bool,
+    /// Whether the redo button has been clicked since the last frame.
+    pub redo_requested: bool,
+    /// Whether the record button has been clicked since the last frame.
+    pub record_requested: bool,
+    /// Whether a session is being recorded.
+    pub recording: bool,
+    /// The statistics of the last tick.
+    pub stats: Option<TickStats>,
 }
 
 impl Framework {
@@ -40,6 +54,7 @@ impl Framework {
         height: u32,
         scale_factor: f32,
         pixels: &pixels::Pixels,
+        materials: Vec<(Material, String)>,
     ) -> Self {
         let max_texture_size = pixels.device().limits().max_texture_dimension_2d as usize;
 
@@ -54,13 +69,19 @@ impl Framework {
         let rpass = RenderPass::new(pixels.device(), pixels.render_texture_format(), 1);
         let textures = TexturesDelta::default();
         let gui = Gui {
-            material: Material::Sand,
+            material: Material::SAND,
+            materials,
+            brush_size: 3,
             tick_interval: 1,
-            failed_locks: 0,
             tick_duration: Duration::from_secs(0),
             frame_duration: Duration::from_secs(0),
             last_frame_time: SystemTime::now(),
             num_threads: 0,
+            undo_requested: false,
+            redo_requested: false,
+            record_requested: false,
+            recording: false,
+            stats: None,
         };
 
         Self {
@@ -147,24 +168,46 @@ impl Gui {
     fn ui(&mut self, ctx: &Context) {
         egui::Window::new("Materials").show(ctx, |ui| {
             ui.vertical(|ui| {
-                let current = &mut self.material;
-                ui.radio_value(current, Material::Air, "Air");
-                ui.radio_value(current, Material::Sand, "Sand");
-                ui.radio_value(current, Material::Water, "Water");
-                ui.radio_value(current, Material::Fire, "Fire");
-                ui.radio_value(current, Material::Wood, "Wood");
-                ui.radio_value(current, Material::Vapor, "Vapor");
-                ui.radio_value(current, Material::Smoke, "Smoke");
-                ui.radio_value(current, Material::SandGenerator, "Sand Generator");
-                ui.radio_value(current, Material::WaterGenerator, "Water Generator");
+                for (material, name) in &self.materials {
+                    ui.radio_value(&mut self.material, *material, name);
+                }
             });
+            ui.label("Brush Size");
+            ui.add(egui::Slider::new(&mut self.brush_size, 1..=16));
+            ui.horizontal(|ui| {
+                self.undo_requested |= ui.button("Undo (Ctrl+Z)").clicked();
+                self.redo_requested |= ui.button("Redo (Ctrl+Y)").clicked();
+            });
+            let record = if self.recording {
+                "Stop Recording (Ctrl+R)"
+            } else {
+                "Record (Ctrl+R)"
+            };
+            self.record_requested |= ui.button(record).clicked();
             ui.label(format!("Threads: {}", self.num_threads));
             ui.label("Tick Interval");
             ui.add(egui::Slider::new(&mut self.tick_interval, 1..=6));
             let fps = 1_000_000 / self.frame_duration.as_micros();
             ui.label(format!("FPS: {}", fps));
             ui.label(format!("Tick Time: {}µs", self.tick_duration.as_micros()));
-            ui.label(format!("Failed locks: {}", self.failed_locks));
         });
+
+        if let Some(stats) = &self.stats {
+            egui::Window::new("Statistics").show(ctx, |ui| {
+                ui.label(format!("Moved: {}", stats.moved));
+                ui.label(format!("Voided: {}", stats.voided));
+                ui.separator();
+                for (material, name) in &self.materials {
+                    let count = stats.count(*material);
+                    if count > 0 {
+                        ui.label(format!("{name}: {count}"));
+                    }
+                }
+                ui.separator();
+                for (outcome, count) in stats.outcomes.named() {
+                    ui.label(format!("{outcome}: {count}"));
+                }
+            });
+        }
     }
 }
@@ -3,13 +3,17 @@ extern crate core;
 mod gui;
 
 use crate::gui::Framework;
-use log::{debug, error};
+use log::{debug, error, info};
 use pixels::{Pixels, SurfaceTexture};
-use simulation::entities::cell_content::Particle;
 use simulation::entities::material::Material;
+use simulation::entities::registry::MaterialRegistry;
+use simulation::history::History;
+use simulation::replay::{Operation, Recorder};
 use simulation::sand_sim::{Cell, Simulation};
-use simulation::universe::{Position, Universe};
-use std::time::{Duration, Instant, SystemTime};
+use simulation::universe::{Anchor, Position, Universe};
+use std::fs::File;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, mem};
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
@@ -18,6 +22,10 @@ use winit_input_helper::WinitInputHelper;
 
 const WIDTH: u32 = 256;
 const HEIGHT: u32 = 256;
+/// The size of a cell on the screen in logical pixels.
+const CELL_SIZE: u32 = 4;
+/// The number of edits that can be undone.
+const HISTORY_CAPACITY: usize = 64;
 
 fn main() {
     env_logger::init();
@@ -26,22 +34,36 @@ fn main() {
 
     let width_usize = WIDTH as usize;
     let height_usize = HEIGHT as usize;
-    let sim = Simulation::new(width_usize, height_usize);
-    let mut fill_area = vec![Material::Air; width_usize * height_usize];
-    fill_area[width_usize / 2] = Material::SandGenerator;
+
+    // additional materials can be loaded from a file given as the first argument
+    let materials = match env::args().nth(1) {
+        Some(path) => MaterialRegistry::load(&path)
+            .unwrap_or_else(|e| panic!("Failed to load materials from {path}: {e}")),
+        None => MaterialRegistry::builtin(),
+    };
+    let names = materials
+        .materials()
+        .map(|(material, behavior)| (material, behavior.name().to_string()))
+        .collect();
+
+    let mut sim = Simulation::with_materials(width_usize, height_usize, rand::random(), materials);
+    let mut fill_area = vec![Material::AIR; width_usize * height_usize];
+    fill_area[width_usize / 2] = Material::SAND_GENERATOR;
 
     // fill lower half with water
     for i in (width_usize * (height_usize / 2))..fill_area.len() {
-        fill_area[i] = Material::Water;
+        fill_area[i] = Material::WATER;
     }
 
     sim.par_fill(&fill_area);
+    sim.set_history(History::new(HISTORY_CAPACITY, false));
+    sim.set_collect_stats(true);
 
     let mut current_tick = 0;
 
     let window = {
         let size = LogicalSize::new(WIDTH, HEIGHT);
-        let scaled_size = LogicalSize::new(WIDTH * 4, HEIGHT * 4);
+        let scaled_size = LogicalSize::new(WIDTH * CELL_SIZE, HEIGHT * CELL_SIZE);
         WindowBuilder::new()
             .with_title("Sandspiel")
             .with_inner_size(scaled_size)
@@ -62,13 +84,17 @@ fn main() {
     #[allow(clippy::cast_possible_truncation)]
     let scale_factor = window.scale_factor() as f32;
 
-    let mut framework = Framework::new(&event_loop, WIDTH, HEIGHT, scale_factor, &pixels);
+    let mut framework = Framework::new(&event_loop, WIDTH, HEIGHT, scale_factor, &pixels, names);
 
     // manually declare num threads for gui
     // NOTE:  might be inaccurate, rayon docs say this value could change over time
     framework.gui.num_threads = Simulation::get_num_threads();
 
     let mut mouse_pos = (-1f32, -1f32);
+    // the end of the last brush stroke, so that fast mouse movements still paint connected lines
+    let mut last_brush_pos: Option<Position> = None;
+    // all changes to the simulation go through the recorder while a session is recorded
+    let mut recorder: Option<Recorder> = None;
 
     event_loop.run(move |event, _, control_flow| {
         // Handle input events
@@ -86,30 +112,68 @@ fn main() {
             if input.mouse_pressed(0) | input.mouse_held(0) {
                 match pixels.window_pos_to_pixel(mouse_pos) {
                     Ok((x, y)) => {
-                        let content = Particle::new(framework.gui.material.clone(), false, 0);
-                        let position = Position::new(x, y);
-                        sim.universe
-                            .get_cell(&position)
-                            .unwrap()
-                            .lock()
-                            .unwrap()
-                            .clone_from(&content);
+                        let to = Position::new(x, y);
+                        let paint = Operation::PaintLine {
+                            from: last_brush_pos.take().unwrap_or_else(|| to.clone()),
+                            to: to.clone(),
+                            thickness: usize::from(framework.gui.brush_size),
+                            material: framework.gui.material,
+                        };
+                        perform(&mut sim, &mut recorder, paint);
+                        last_brush_pos = Some(to);
                     }
                     Err((x, y)) => {
+                        last_brush_pos = None;
                         debug!("mouse position outside of window!: {:?}:{:?}", x, y)
                     }
                 }
             }
 
+            // every brush stroke is undone at once
+            if input.mouse_released(0) {
+                last_brush_pos = None;
+                perform(&mut sim, &mut recorder, Operation::CommitEdit);
+            }
+
+            let undo = input.held_control() && input.key_pressed(VirtualKeyCode::Z);
+            if mem::take(&mut framework.gui.undo_requested) || undo {
+                perform(&mut sim, &mut recorder, Operation::Undo);
+            }
+
+            let redo = input.held_control() && input.key_pressed(VirtualKeyCode::Y);
+            if mem::take(&mut framework.gui.redo_requested) || redo {
+                perform(&mut sim, &mut recorder, Operation::Redo);
+            }
+
+            let record = input.held_control() && input.key_pressed(VirtualKeyCode::R);
+            if mem::take(&mut framework.gui.record_requested) || record {
+                toggle_recording(&mut sim, &mut recorder);
+                framework.gui.recording = recorder.is_some();
+            }
+
             // Update the scale factor
             if let Some(scale_factor) = input.scale_factor() {
                 framework.scale_factor(scale_factor);
             }
 
-            // Resize the window
+            // Resize the window, and grow or shrink the world to match it
             if let Some(size) = input.window_resized() {
                 pixels.resize_surface(size.width, size.height);
                 framework.resize(size.width, size.height);
+
+                let logical = size.to_logical::<u32>(window.scale_factor());
+                let width = (logical.width / CELL_SIZE).max(1);
+                let height = (logical.height / CELL_SIZE).max(1);
+                let universe = sim.universe();
+                if (width as usize, height as usize) != (universe.width, universe.height) {
+                    let resize = Operation::Resize {
+                        width: width as usize,
+                        height: height as usize,
+                        anchor: Anchor::Bottom,
+                    };
+                    perform(&mut sim, &mut recorder, resize);
+                    pixels.resize_buffer(width, height);
+                }
             }
 
             current_tick += 1;
@@ -117,9 +181,11 @@ fn main() {
 
             if current_tick == 0 {
                 // Update internal state and request a redraw
-                framework.gui.tick_duration = measure_time(|| {
-                    framework.gui.failed_locks = sim.par_tick();
+                framework.gui.tick_duration = measure_time(|| match &mut recorder {
+                    Some(recorder) => recorder.tick(&mut sim),
+                    None => sim.par_tick(),
                 });
+                framework.gui.stats = sim.stats().cloned();
 
                 let now = SystemTime::now();
                 framework.gui.frame_duration =
@@ -137,7 +203,7 @@ fn main() {
             // Draw the current frame
             Event::RedrawRequested(_) => {
                 // Draw the world
-                draw(&sim.universe, pixels.get_frame_mut());
+                draw(sim.universe(), sim.materials(), pixels.get_frame_mut());
 
                 // Prepare egui
                 framework.prepare(&window);
@@ -166,35 +232,49 @@ fn main() {
     });
 }
 
-fn draw(universe: &Universe<Cell>, screen: &mut [u8]) {
+fn draw(universe: &Universe<Cell>, materials: &MaterialRegistry, screen: &mut [u8]) {
     for (cell, pixel) in universe.area.iter().zip(screen.chunks_exact_mut(4)) {
-        pixel.copy_from_slice(cell_to_color(&cell.lock().unwrap()));
+        pixel.copy_from_slice(&materials.get(cell.material).color());
     }
 }
 
-fn cell_to_color(cell: &Particle) -> &[u8; 4] {
-    match cell.material {
-        Material::Sand => &SAND_COLOR,
-        Material::SandGenerator => &SAND_GENERATOR_COLOR,
-        Material::Water => &WATER_COLOR,
-        Material::WaterGenerator => &WATER_GENERATOR_COLOR,
-        Material::Air => &AIR_COLOR,
-        Material::Fire => &FIRE_COLOR,
-        Material::Smoke => &SMOKE_COLOR,
-        Material::Vapor => &VAPOR_COLOR,
-        Material::Wood => &WOOD_COLOR,
+/// Performs an [Operation] on the simulation, recording it if a session is being recorded.
+fn perform(sim: &mut Simulation, recorder: &mut Option<Recorder>, operation: Operation) {
+    match recorder {
+        Some(recorder) => recorder.apply(sim, operation),
+        None => operation.apply(sim),
     }
 }
 
-const AIR_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0x00];
-const WATER_COLOR: [u8; 4] = [0, 0, 0xff, 0xff];
-const SAND_COLOR: [u8; 4] = [0xff, 0xff, 0, 0xff];
-const WATER_GENERATOR_COLOR: [u8; 4] = [0, 0xff, 0xff, 0xff];
-const SAND_GENERATOR_COLOR: [u8; 4] = [0xff, 0, 0xff, 0xff];
-const FIRE_COLOR: [u8; 4] = [0xff, 0, 0, 0xff];
-const SMOKE_COLOR: [u8; 4] = [0x7F, 0x7F, 0x7F, 0xff];
-const VAPOR_COLOR: [u8; 4] = [0x7F, 0x7F, 0xFF, 0xff];
-const WOOD_COLOR: [u8; 4] = [0xDE, 0xB8, 0x87, 0xff];
+/// Starts recording a session, or stops recording and saves the replay to the working directory.
+fn toggle_recording(sim: &mut Simulation, recorder: &mut Option<Recorder>) {
+    match recorder.take() {
+        Some(finished) => {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let path = format!("sandspiel-{secs}.replay");
+
+            let result = File::create(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|mut file| {
+                    finished
+                        .finish()
+                        .write(&mut file)
+                        .map_err(|e| e.to_string())
+                });
+            match result {
+                Ok(()) => info!("Saved replay to {path}"),
+                Err(e) => error!("Failed to save replay to {path}: {e}"),
+            }
+        }
+        None => match Recorder::start(sim, true) {
+            Ok(started) => *recorder = Some(started),
+            Err(e) => error!("Failed to start recording: {e}"),
+        },
+    }
+}
 
 fn measure_time<F>(f: F) -> Duration
 where
@@ -0,0 +1,383 @@
+//! Runs a scenario without any window, e.g. for batches of experiments on headless machines.
+
+use simulation::entities::material::Material;
+use simulation::entities::registry::MaterialRegistry;
+use simulation::export::write_png;
+use simulation::import::{read_png, Palette};
+use simulation::sand_sim::{Simulation, MAX_CELLS};
+use simulation::stats::TickStats;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::{env, io};
+
+const USAGE: &str = "\
+Usage: sandspiel-cli <SCENARIO> [OPTIONS]
+
+Runs a scenario for a number of ticks and writes the final world.
+
+SCENARIO is a snapshot (*.snap), a scene drawn as PNG (*.png) or one of the presets
+'sandfall', 'basin' and 'bonfire'.
+
+Options:
+  --seed <SEED>          Seed of the simulation [default: 0]
+  --ticks <TICKS>        Number of ticks to run [default: 100]
+  --size <WxH>           Size of a preset [default: 100x100]
+  --materials <PATH>     Materials to load instead of the built-in ones
+  --output <PATH>        Final world, as PNG (*.png) or snapshot (any other extension)
+  --stats <PATH>         Statistics of each tick as CSV
+  --parallel             Use multithreading where possible
+  --check-invariants     Check invariants after each tick and fail if any is violated
+  -h, --help             Print this help";
+
+/// The size of presets unless given with `--size`.
+const DEFAULT_SIZE: (usize, usize) = (100, 100);
+
+/// The command-line arguments.
+struct Args {
+    scenario: String,
+    seed: u64,
+    ticks: u64,
+    size: (usize, usize),
+    materials: Option<String>,
+    output: Option<String>,
+    stats: Option<String>,
+    parallel: bool,
+    check_invariants: bool,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parses the command-line arguments, or returns [None] if the help was requested.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut scenario = None;
+    let mut parsed = Args {
+        scenario: String::new(),
+        seed: 0,
+        ticks: 100,
+        size: DEFAULT_SIZE,
+        materials: None,
+        output: None,
+        stats: None,
+        parallel: false,
+        check_invariants: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--seed" => parsed.seed = parse_number(&value()?, "seed")?,
+            "--ticks" => parsed.ticks = parse_number(&value()?, "tick count")?,
+            "--size" => {
+                let size = value()?;
+                let (width, height) = size
+                    .split_once('x')
+                    .ok_or_else(|| format!("invalid size '{size}', expected e.g. 100x100"))?;
+                let (width, height) = (
+                    parse_number::<usize>(width, "width")?,
+                    parse_number::<usize>(height, "height")?,
+                );
+                match width.checked_mul(height) {
+                    Some(cells) if cells <= MAX_CELLS => {}
+                    _ => {
+                        return Err(format!(
+                            "size '{size}' is too large, at most {MAX_CELLS} cells are supported"
+                        ))
+                    }
+                }
+                parsed.size = (width, height);
+            }
+            "--materials" => parsed.materials = Some(value()?),
+            "--output" => parsed.output = Some(value()?),
+            "--stats" => parsed.stats = Some(value()?),
+            "--parallel" => parsed.parallel = true,
+            "--check-invariants" => parsed.check_invariants = true,
+            flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+            _ if scenario.is_some() => return Err(format!("unexpected argument {arg}")),
+            _ => scenario = Some(arg),
+        }
+    }
+
+    parsed.scenario = scenario.ok_or("missing scenario")?;
+    Ok(Some(parsed))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {name} '{value}'"))
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let materials = match &args.materials {
+        Some(path) => MaterialRegistry::load(path)
+            .map_err(|e| format!("failed to load materials from {path}: {e}"))?,
+        None => MaterialRegistry::builtin(),
+    };
+    let mut sim = load_scenario(args, materials)?;
+    sim.set_check_invariants(args.check_invariants);
+    let mut violations = 0;
+
+    let mut stats = match &args.stats {
+        Some(path) => {
+            let mut writer = BufWriter::new(create(path)?);
+            write_stats_header(&mut writer, sim.materials())?;
+            sim.set_collect_stats(true);
+            Some(writer)
+        }
+        None => None,
+    };
+
+    for tick in 1..=args.ticks {
+        if args.parallel {
+            sim.par_tick();
+        } else {
+            sim.tick();
+        }
+
+        if let (Some(writer), Some(tick_stats)) = (&mut stats, sim.stats()) {
+            write_stats(writer, tick, tick_stats, sim.materials())?;
+        }
+
+        for violation in sim.take_violations() {
+            eprintln!("{violation}");
+            violations += 1;
+        }
+    }
+
+    if let Some(mut writer) = stats {
+        writer.flush()?;
+    }
+
+    if let Some(path) = &args.output {
+        let mut writer = BufWriter::new(create(path)?);
+        if has_extension(path, "png") {
+            write_png(&mut writer, sim.universe(), sim.materials())?;
+        } else {
+            sim.save(&mut writer)?;
+        }
+        writer.flush()?;
+    }
+
+    if violations > 0 {
+        return Err(format!("{violations} invariants were violated").into());
+    }
+
+    Ok(())
+}
+
+/// Sets up the simulation of a snapshot, a PNG scene or a preset.
+fn load_scenario(args: &Args, materials: MaterialRegistry) -> Result<Simulation, Box<dyn Error>> {
+    let path = &args.scenario;
+
+    if has_extension(path, "snap") {
+        let mut reader = BufReader::new(open(path)?);
+        let sim = Simulation::load_with_materials(&mut reader, args.seed, materials)
+            .map_err(|e| format!("failed to load snapshot {path}: {e}"))?;
+        return Ok(sim);
+    }
+
+    if has_extension(path, "png") {
+        let palette = Palette::from_materials(&materials);
+        let scene = read_png(BufReader::new(open(path)?), &palette)
+            .map_err(|e| format!("failed to import scene from {path}: {e}"))?;
+        let mut sim = Simulation::with_materials(scene.width, scene.height, args.seed, materials);
+        sim.try_par_fill(&scene.area)?;
+        return Ok(sim);
+    }
+
+    let (width, height) = args.size;
+    let area = preset(path, width, height).ok_or_else(|| format!("unknown scenario '{path}'"))?;
+    let mut sim = Simulation::with_materials(width, height, args.seed, materials);
+    sim.try_par_fill(&area)?;
+
+    Ok(sim)
+}
+
+/// Generates the area of a preset scenario of the given size.
+fn preset(name: &str, width: usize, height: usize) -> Option<Vec<Material>> {
+    let mut area = vec![Material::AIR; width * height];
+    let mut rows = |from: usize, material: Material| {
+        let start = (width * from).min(area.len());
+        area[start..].fill(material);
+    };
+
+    match name {
+        // a single generator above a floor of wood
+        "sandfall" => {
+            rows(height.saturating_sub(1), Material::WOOD);
+            if let Some(cell) = area.get_mut(width / 2) {
+                *cell = Material::SAND_GENERATOR;
+            }
+        }
+        // a pool of water above a layer of sand
+        "basin" => {
+            rows(height / 2, Material::WATER);
+            rows(height - height / 4, Material::SAND);
+        }
+        // a fire on top of a pile of wood
+        "bonfire" => {
+            rows(height - height / 4, Material::WOOD);
+            if let Some(cell) =
+                area.get_mut(width * (height - height / 4).saturating_sub(1) + width / 2)
+            {
+                *cell = Material::FIRE;
+            }
+        }
+        _ => return None,
+    }
+
+    Some(area)
+}
+
+fn write_stats_header(writer: &mut impl Write, materials: &MaterialRegistry) -> io::Result<()> {
+    write!(writer, "tick,moved,voided")?;
+    for (_, behavior) in materials.materials() {
+        write!(writer, ",{}", behavior.name())?;
+    }
+    for (outcome, _) in TickStats::default().outcomes.named() {
+        write!(writer, ",{outcome}")?;
+    }
+    writeln!(writer)
+}
+
+fn write_stats(
+    writer: &mut impl Write,
+    tick: u64,
+    stats: &TickStats,
+    materials: &MaterialRegistry,
+) -> io::Result<()> {
+    write!(writer, "{tick},{},{}", stats.moved, stats.voided)?;
+    for (material, _) in materials.materials() {
+        write!(writer, ",{}", stats.count(material))?;
+    }
+    for (_, count) in stats.outcomes.named() {
+        write!(writer, ",{count}")?;
+    }
+    writeln!(writer)
+}
+
+fn has_extension(path: &str, extension: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+fn open(path: &str) -> Result<File, String> {
+    File::open(path).map_err(|e| format!("failed to open {path}: {e}"))
+}
+
+fn create(path: &str) -> Result<File, String> {
+    File::create(path).map_err(|e| format!("failed to create {path}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Args>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let args = parse(&[
+            "basin",
+            "--seed",
+            "7",
+            "--ticks",
+            "5",
+            "--size",
+            "30x20",
+            "--output",
+            "out.png",
+            "--parallel",
+        ])
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(args.scenario, "basin");
+        assert_eq!((args.seed, args.ticks, args.size), (7, 5, (30, 20)));
+        assert_eq!(args.output.as_deref(), Some("out.png"));
+        assert!(args.parallel && !args.check_invariants);
+        assert_eq!((args.materials, args.stats), (None, None));
+    }
+
+    #[test]
+    fn defaults_are_used_for_missing_options() {
+        let args = parse(&["sandfall"]).unwrap().unwrap();
+
+        assert_eq!((args.seed, args.ticks, args.size), (0, 100, DEFAULT_SIZE));
+        assert!(!args.parallel);
+    }
+
+    #[test]
+    fn help_is_requested() {
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["basin", "-h"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        for args in [
+            &[][..],
+            &["basin", "bonfire"],
+            &["basin", "--speed", "3"],
+            &["basin", "--seed"],
+            &["basin", "--ticks", "many"],
+            &["basin", "--size", "30"],
+            &["basin", "--size", "30x"],
+            &["basin", "--size", "-3x20"],
+            &["basin", "--size", "100000000000x100000000000"],
+            &["basin", "--size", "5000x5000"],
+        ] {
+            assert!(parse(args).is_err(), "{args:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn unknown_scenarios_are_rejected() {
+        let args = parse(&["volcano"]).unwrap().unwrap();
+        let result = load_scenario(&args, MaterialRegistry::builtin());
+
+        let error = result.err().expect("scenario was loaded").to_string();
+        assert_eq!(error, "unknown scenario 'volcano'");
+    }
+
+    #[test]
+    fn presets_fill_the_whole_area() {
+        for name in ["sandfall", "basin", "bonfire"] {
+            for (width, height) in [(1, 1), (3, 2), (40, 30)] {
+                let area = preset(name, width, height).unwrap();
+                assert_eq!(area.len(), width * height);
+            }
+        }
+    }
+}
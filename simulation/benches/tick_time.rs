@@ -1,7 +1,6 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
-use rayon::prelude::IntoParallelRefIterator;
 use simulation::entities::material::Material;
 use simulation::sand_sim::Simulation;
 use std::fs::File;
@@ -11,6 +10,8 @@ const WIDTH: usize = 1024;
 const HEIGHT: usize = 1024;
 const SIZE_BASE: usize = 32;
 const SIZE_MAX_MUL: usize = 32;
+/// The number of ticks after which most of a generated area has come to rest.
+const SETTLE_TICKS: usize = 500;
 
 fn setup_simulation(width: usize, height: usize) -> Simulation {
     Simulation::new(width, height)
@@ -18,13 +19,13 @@ fn setup_simulation(width: usize, height: usize) -> Simulation {
 
 fn gen_area(width: usize, height: usize) -> Vec<Material> {
     let materials = [
-        Material::Air,
-        Material::Water,
-        Material::Fire,
-        Material::Sand,
-        Material::Vapor,
-        Material::Smoke,
-        Material::Wood,
+        Material::AIR,
+        Material::WATER,
+        Material::FIRE,
+        Material::SAND,
+        Material::VAPOR,
+        Material::SMOKE,
+        Material::WOOD,
     ];
 
     let size = width * height;
@@ -33,7 +34,7 @@ fn gen_area(width: usize, height: usize) -> Vec<Material> {
 
     let mut fill_area = Vec::with_capacity(size);
     for _ in 0..size {
-        fill_area.push(materials.choose(&mut rng).unwrap().clone());
+        fill_area.push(*materials.choose(&mut rng).unwrap());
     }
 
     fill_area
@@ -43,7 +44,7 @@ fn bench_tick(c: &mut Criterion) {
     let fill_area = load_fill_area(SIZE_BASE, SIZE_MAX_MUL);
 
     c.bench_function("tick", |b| {
-        let sim = setup_simulation(WIDTH, HEIGHT);
+        let mut sim = setup_simulation(WIDTH, HEIGHT);
 
         sim.par_fill(&fill_area);
 
@@ -57,7 +58,7 @@ fn bench_par_tick(c: &mut Criterion) {
     let fill_area = load_fill_area(SIZE_BASE, SIZE_MAX_MUL);
 
     c.bench_function("par_tick", |b| {
-        let sim = setup_simulation(WIDTH, HEIGHT);
+        let mut sim = setup_simulation(WIDTH, HEIGHT);
 
         sim.par_fill(&fill_area);
 
@@ -67,6 +68,23 @@ fn bench_par_tick(c: &mut Criterion) {
     });
 }
 
+fn bench_par_tick_settled(c: &mut Criterion) {
+    let fill_area = load_fill_area(SIZE_BASE, SIZE_MAX_MUL);
+
+    c.bench_function("par_tick_settled", |b| {
+        let mut sim = setup_simulation(WIDTH, HEIGHT);
+
+        sim.par_fill(&fill_area);
+        for _ in 0..SETTLE_TICKS {
+            sim.par_tick();
+        }
+
+        b.iter(|| {
+            sim.par_tick();
+        });
+    });
+}
+
 fn bench_compare_tick(c: &mut Criterion) {
     let base: usize = SIZE_BASE;
     let max_mul: usize = SIZE_MAX_MUL;
@@ -78,9 +96,9 @@ fn bench_compare_tick(c: &mut Criterion) {
     for i in 1..=max_mul {
         let current = base * i;
         group.bench_with_input(BenchmarkId::new("cmp_tick", current), &current, |b, i| {
-            let sim = setup_simulation(current, current);
+            let mut sim = setup_simulation(*i, *i);
 
-            sim.par_fill(&fill_area[0..(current ^ 2)]);
+            sim.par_fill(&fill_area[0..(i * i)]);
 
             b.iter(|| {
                 sim.tick();
@@ -91,9 +109,9 @@ fn bench_compare_tick(c: &mut Criterion) {
             BenchmarkId::new("cmp_par_tick", current),
             &current,
             |b, i| {
-                let sim = setup_simulation(current, current);
+                let mut sim = setup_simulation(*i, *i);
 
-                sim.par_fill(&fill_area[0..(current ^ 2)]);
+                sim.par_fill(&fill_area[0..(i * i)]);
 
                 b.iter(|| {
                     sim.par_tick();
@@ -117,19 +135,7 @@ fn load_fill_area(base: usize, max_mul: usize) -> Vec<Material> {
 
             let fill_area_u8: Vec<u8> = fill_area
                 .iter()
-                .map(|mat| -> u8 {
-                    match mat {
-                        Material::Sand => 0,
-                        Material::SandGenerator => 1,
-                        Material::Water => 2,
-                        Material::WaterGenerator => 3,
-                        Material::Air => 4,
-                        Material::Fire => 5,
-                        Material::Smoke => 6,
-                        Material::Vapor => 7,
-                        Material::Wood => 8,
-                    }
-                })
+                .map(|mat| u8::try_from(mat.id()).unwrap())
                 .collect();
 
             file.write_all(&fill_area_u8).unwrap();
@@ -145,23 +151,16 @@ fn load_fill_area(base: usize, max_mul: usize) -> Vec<Material> {
 
     let fill_area: Vec<Material> = fill_area_u8
         .iter()
-        .map(|raw_mat| -> Material {
-            match raw_mat {
-                0 => Material::Sand,
-                1 => Material::SandGenerator,
-                2 => Material::Water,
-                3 => Material::WaterGenerator,
-                4 => Material::Air,
-                5 => Material::Fire,
-                6 => Material::Smoke,
-                7 => Material::Vapor,
-                8 => Material::Wood,
-                _ => panic!("Unknown Material ID: {raw_mat}"),
-            }
-        })
+        .map(|raw_mat| Material::from_id(u16::from(*raw_mat)))
         .collect();
     fill_area
 }
 
-criterion_group!(benches, bench_par_tick,);
+criterion_group!(
+    benches,
+    bench_tick,
+    bench_par_tick,
+    bench_par_tick_settled,
+    bench_compare_tick
+);
 criterion_main!(benches);
@@ -0,0 +1,179 @@
+//! The shapes that can be painted into a [Universe], see [Simulation::paint_circle] and friends.
+//!
+//! All shapes are clipped to the bounds of the universe, so they may extend beyond its edges.
+//!
+//! [Universe]: crate::universe::Universe
+//! [Simulation::paint_circle]: crate::sand_sim::Simulation::paint_circle
+
+use crate::universe::Position;
+use std::ops::RangeInclusive;
+
+/// Returns the positions within a circle around `center`, which is a single cell for a radius of
+/// zero.
+pub(crate) fn circle(
+    center: &Position,
+    radius: usize,
+    width: usize,
+    height: usize,
+) -> impl Iterator<Item = Position> + '_ {
+    let squared = radius.saturating_mul(radius);
+
+    rect_cells(
+        clip(
+            center.x.saturating_sub(radius),
+            center.x.saturating_add(radius),
+            width,
+        ),
+        clip(
+            center.y.saturating_sub(radius),
+            center.y.saturating_add(radius),
+            height,
+        ),
+    )
+    .filter(move |pos| {
+        let (dx, dy) = (pos.x.abs_diff(center.x), pos.y.abs_diff(center.y));
+        dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy)) <= squared
+    })
+}
+
+/// Returns the positions within the rectangle spanned by two opposite corners, both included.
+pub(crate) fn rect(
+    from: &Position,
+    to: &Position,
+    width: usize,
+    height: usize,
+) -> impl Iterator<Item = Position> {
+    rect_cells(
+        clip(from.x.min(to.x), from.x.max(to.x), width),
+        clip(from.y.min(to.y), from.y.max(to.y), height),
+    )
+}
+
+/// Returns the positions whose centers are at most half the thickness away from the line between
+/// `from` and `to`, so that the line has round caps. A thickness of zero is treated as one.
+pub(crate) fn line<'a>(
+    from: &'a Position,
+    to: &'a Position,
+    thickness: usize,
+    width: usize,
+    height: usize,
+) -> impl Iterator<Item = Position> + 'a {
+    let reach = thickness.max(1) as f32 / 2.0;
+    let margin = reach.ceil() as usize;
+
+    let (x0, y0) = (from.x as f32, from.y as f32);
+    let (dx, dy) = (to.x as f32 - x0, to.y as f32 - y0);
+    let squared_len = dx * dx + dy * dy;
+
+    rect_cells(
+        clip(
+            from.x.min(to.x).saturating_sub(margin),
+            from.x.max(to.x).saturating_add(margin),
+            width,
+        ),
+        clip(
+            from.y.min(to.y).saturating_sub(margin),
+            from.y.max(to.y).saturating_add(margin),
+            height,
+        ),
+    )
+    .filter(move |pos| {
+        let (px, py) = (pos.x as f32 - x0, pos.y as f32 - y0);
+        // the share of the line up to the point closest to the cell
+        let t = if squared_len == 0.0 {
+            0.0
+        } else {
+            ((px * dx + py * dy) / squared_len).clamp(0.0, 1.0)
+        };
+        let (ex, ey) = (px - t * dx, py - t * dy);
+
+        ex * ex + ey * ey <= reach * reach
+    })
+}
+
+/// Clips the range from `start` to `end`, both included, to the range from zero to `len`,
+/// excluded. Returns [None] if nothing is left.
+fn clip(start: usize, end: usize, len: usize) -> Option<RangeInclusive<usize>> {
+    let end = end.min(len.checked_sub(1)?);
+
+    (start <= end).then_some(start..=end)
+}
+
+/// Returns all positions of a rectangle, row by row.
+fn rect_cells(
+    xs: Option<RangeInclusive<usize>>,
+    ys: Option<RangeInclusive<usize>>,
+) -> impl Iterator<Item = Position> {
+    xs.zip(ys)
+        .into_iter()
+        .flat_map(|(xs, ys)| ys.flat_map(move |y| xs.clone().map(move |x| Position::new(x, y))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn cells(positions: impl Iterator<Item = Position>) -> BTreeSet<(usize, usize)> {
+        positions.map(|pos| (pos.x, pos.y)).collect()
+    }
+
+    /// Returns all cells of the rectangle spanned by two ranges.
+    fn block(xs: RangeInclusive<usize>, ys: RangeInclusive<usize>) -> BTreeSet<(usize, usize)> {
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y))).collect()
+    }
+
+    #[test]
+    fn circles_are_clipped_at_the_edges() {
+        let corner = cells(circle(&Position::new(0, 0), 2, 10, 10));
+        assert_eq!(
+            corner,
+            BTreeSet::from([(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (0, 2)])
+        );
+
+        let single = cells(circle(&Position::new(9, 9), 0, 10, 10));
+        assert_eq!(single, BTreeSet::from([(9, 9)]));
+
+        assert_eq!(circle(&Position::new(20, 20), 2, 10, 10).count(), 0);
+    }
+
+    #[test]
+    fn rectangles_are_clipped_at_the_edges() {
+        let clipped = cells(rect(&Position::new(20, 3), &Position::new(8, 8), 10, 10));
+        assert_eq!(clipped, block(8..=9, 3..=8));
+
+        assert_eq!(
+            rect(&Position::new(10, 0), &Position::new(12, 4), 10, 10).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn thick_lines_have_round_caps() {
+        let line = cells(line(&Position::new(2, 5), &Position::new(12, 5), 3, 20, 20));
+
+        // the caps reach one cell beyond both ends, but not diagonally
+        assert_eq!(line, block(1..=13, 4..=6));
+    }
+
+    #[test]
+    fn thin_lines_are_one_cell_wide() {
+        for thickness in [0, 1] {
+            let diagonal = cells(line(
+                &Position::new(0, 0),
+                &Position::new(3, 3),
+                thickness,
+                10,
+                10,
+            ));
+            assert_eq!(diagonal, BTreeSet::from([(0, 0), (1, 1), (2, 2), (3, 3)]));
+        }
+    }
+
+    #[test]
+    fn thick_lines_are_clipped_at_the_edges() {
+        let clipped = cells(line(&Position::new(0, 0), &Position::new(0, 9), 5, 3, 10));
+
+        assert_eq!(clipped, block(0..=2, 0..=9));
+    }
+}
@@ -0,0 +1,376 @@
+use crate::entities::cell_content::Particle;
+use crate::universe::{Boundaries, Position, Universe};
+use std::mem;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The minimal edge length of the chunks a [Universe] is split into.
+pub(crate) const CHUNK_SIZE: usize = 32;
+
+/// The highest speed a [Particle] can reach, see [Velocity::speed].
+///
+/// A particle moves at most this many cells per tick and then interacts with a direct neighbor.
+/// Keeping that reach below half of [CHUNK_SIZE] guarantees that two chunks which are processed in
+/// the same phase never touch the same cell.
+///
+/// [Velocity::speed]: crate::entities::cell_content::Velocity::speed
+pub(crate) const MAX_VELOCITY: f32 = ((CHUNK_SIZE - 2) / 2) as f32;
+
+/// A rectangular part of a [Universe].
+#[derive(Clone, Debug)]
+pub(crate) struct Region {
+    pub x: Range<usize>,
+    pub y: Range<usize>,
+}
+
+impl Region {
+    pub fn contains(&self, pos: &Position) -> bool {
+        self.x.contains(&pos.x) && self.y.contains(&pos.y)
+    }
+
+    /// Returns all positions of the region, ordered from the bottom right to the top left.
+    pub fn positions_rev(&self) -> impl Iterator<Item = Position> {
+        let columns = self.x.clone();
+
+        self.y
+            .clone()
+            .rev()
+            .flat_map(move |y| columns.clone().rev().map(move |x| Position { x, y }))
+    }
+}
+
+/// Splits a [Universe] into chunks, which can be simulated in parallel.
+///
+/// Chunks are assigned to one of four phases like the fields of a checkerboard, with each phase
+/// being made of every other chunk in both directions. The chunks of one phase are at least
+/// [CHUNK_SIZE] cells apart from each other, so they can be simulated at the same time without
+/// any locking. Along axes that wrap around, there is an even number of chunks, so that this also
+/// holds for the first and the last chunk.
+///
+/// Chunks also keep track of whether their cells have changed. A chunk that has been at rest for
+/// [SLEEP_DELAY] ticks falls asleep and is skipped, until it or one of its neighbors changes.
+#[derive(Debug)]
+pub(crate) struct Chunks {
+    columns: Vec<Range<usize>>,
+    rows: Vec<Range<usize>>,
+    /// The column of chunks each x coordinate belongs to.
+    column_of: Vec<usize>,
+    /// The row of chunks each y coordinate belongs to.
+    row_of: Vec<usize>,
+    /// Whether the first and last column of chunks are neighbors, see [Boundaries::wraps_x].
+    wraps_x: bool,
+    /// Whether the first and last row of chunks are neighbors, see [Boundaries::wraps_y].
+    wraps_y: bool,
+    /// The number of consecutive ticks each chunk has been at rest for.
+    idle_ticks: Vec<u8>,
+    /// Whether each chunk has changed since the last call of [Chunks::settle].
+    changed: Vec<AtomicBool>,
+    /// Whether each chunk is never simulated, even if it changes, see [Chunks::freeze_unless].
+    frozen: Vec<bool>,
+}
+
+/// The number of ticks a chunk has to be at rest before it falls asleep.
+///
+/// Many collisions only happen by chance, so a chunk that did not change during a single tick
+/// might still change during the next one.
+pub(crate) const SLEEP_DELAY: u8 = 16;
+
+impl Chunks {
+    pub fn new(width: usize, height: usize, boundaries: &Boundaries) -> Self {
+        let (wraps_x, wraps_y) = (boundaries.wraps_x(), boundaries.wraps_y());
+        let columns = Self::split(width, wraps_x);
+        let rows = Self::split(height, wraps_y);
+        let count = columns.len() * rows.len();
+
+        Self {
+            column_of: Self::lookup(&columns),
+            row_of: Self::lookup(&rows),
+            wraps_x,
+            wraps_y,
+            columns,
+            rows,
+            idle_ticks: vec![0; count],
+            changed: (0..count).map(|_| AtomicBool::new(false)).collect(),
+            frozen: vec![false; count],
+        }
+    }
+
+    /// Splits a length into ranges of at least [CHUNK_SIZE], unless the length itself is smaller.
+    /// The number of ranges is even if the axis wraps around and there is more than one.
+    fn split(len: usize, wraps: bool) -> Vec<Range<usize>> {
+        let mut count = (len / CHUNK_SIZE).max(1);
+        if wraps && count > 1 {
+            count -= count % 2;
+        }
+
+        (0..count)
+            .map(|i| (i * len / count)..((i + 1) * len / count))
+            .collect()
+    }
+
+    /// Maps every coordinate to the index of the range it belongs to.
+    fn lookup(ranges: &[Range<usize>]) -> Vec<usize> {
+        ranges
+            .iter()
+            .enumerate()
+            .flat_map(|(i, range)| range.clone().map(move |_| i))
+            .collect()
+    }
+
+    fn index(&self, column: usize, row: usize) -> usize {
+        row * self.columns.len() + column
+    }
+
+    fn is_awake(&self, index: usize) -> bool {
+        !self.frozen[index]
+            && (self.idle_ticks[index] < SLEEP_DELAY || self.changed[index].load(Ordering::Relaxed))
+    }
+
+    /// Returns the awake chunks of the given phase (0 to 3).
+    pub fn phase(&self, phase: usize) -> Vec<Region> {
+        let (col_offset, row_offset) = (phase % 2, phase / 2);
+
+        (row_offset..self.rows.len())
+            .step_by(2)
+            .flat_map(|row| {
+                (col_offset..self.columns.len())
+                    .step_by(2)
+                    .map(move |column| (column, row))
+            })
+            .filter(|&(column, row)| self.is_awake(self.index(column, row)))
+            .map(|(column, row)| Region {
+                x: self.columns[column].clone(),
+                y: self.rows[row].clone(),
+            })
+            .collect()
+    }
+
+    /// Returns all awake chunks.
+    pub fn awake(&self) -> Vec<Region> {
+        (0..4).flat_map(|phase| self.phase(phase)).collect()
+    }
+
+    /// Returns the positions of all awake chunks, ordered from the bottom right to the top left.
+    pub fn awake_positions_rev(&self) -> impl Iterator<Item = Position> + '_ {
+        (0..self.rows.len()).rev().flat_map(move |row| {
+            let columns: Vec<usize> = (0..self.columns.len())
+                .rev()
+                .filter(|&column| self.is_awake(self.index(column, row)))
+                .flat_map(|column| self.columns[column].clone().rev())
+                .collect();
+
+            self.rows[row]
+                .clone()
+                .rev()
+                .flat_map(move |y| columns.clone().into_iter().map(move |x| Position { x, y }))
+        })
+    }
+
+    /// Marks the chunk of a changed cell, as well as the chunks of its neighbors, as changed.
+    pub fn mark_changed(&self, pos: &Position) {
+        let columns = Self::containing(&self.column_of, pos.x, self.wraps_x);
+        let rows = Self::containing(&self.row_of, pos.y, self.wraps_y);
+
+        for row in rows {
+            for column in columns.clone() {
+                let changed = &self.changed[self.index(column, row)];
+
+                // avoid writing to memory that is shared between threads where possible
+                if !changed.load(Ordering::Relaxed) {
+                    changed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Returns the chunks that contain the given coordinate or one of its neighbors, which
+    /// includes the chunk at the other end of the axis for the outermost coordinates if it wraps
+    /// around.
+    fn containing(
+        lookup: &[usize],
+        coordinate: usize,
+        wraps: bool,
+    ) -> impl Iterator<Item = usize> + Clone {
+        let end = lookup.len() - 1;
+        let first = lookup[coordinate.saturating_sub(1)];
+        let last = lookup[(coordinate + 1).min(end)];
+        let wrapped = match coordinate {
+            _ if !wraps => None,
+            0 => Some(lookup[end]),
+            _ if coordinate == end => Some(lookup[0]),
+            _ => None,
+        };
+
+        (first..=last).chain(wrapped)
+    }
+
+    /// Marks all chunks as changed.
+    pub fn mark_all_changed(&mut self) {
+        for changed in &mut self.changed {
+            *changed.get_mut() = true;
+        }
+    }
+
+    /// Freezes all chunks that `awake` returns false for, so that they are never simulated, not
+    /// even once they change. Changes to them are still tracked, see [Chunks::has_changed].
+    pub fn freeze_unless(&mut self, awake: impl Fn(&Region) -> bool) {
+        for (row, y) in self.rows.iter().enumerate() {
+            for (column, x) in self.columns.iter().enumerate() {
+                let region = Region {
+                    x: x.clone(),
+                    y: y.clone(),
+                };
+                let index = self.index(column, row);
+                self.frozen[index] = !awake(&region);
+            }
+        }
+    }
+
+    /// Returns whether any chunk that overlaps with the given [Region] changed during the last
+    /// tick, i.e. before the last call of [Chunks::settle].
+    pub fn has_changed(&self, region: &Region) -> bool {
+        let overlaps = |a: &Range<usize>, b: &Range<usize>| a.start < b.end && b.start < a.end;
+
+        self.rows.iter().enumerate().any(|(row, y)| {
+            overlaps(y, &region.y)
+                && self.columns.iter().enumerate().any(|(column, x)| {
+                    overlaps(x, &region.x) && self.idle_ticks[self.index(column, row)] == 0
+                })
+        })
+    }
+
+    /// Updates the idle time of all chunks at the end of a tick.
+    pub fn settle(&mut self) {
+        for (idle_ticks, changed) in self.idle_ticks.iter_mut().zip(&mut self.changed) {
+            if mem::take(changed.get_mut()) {
+                *idle_ticks = 0;
+            } else {
+                *idle_ticks = idle_ticks.saturating_add(1);
+            }
+        }
+    }
+}
+
+/// Grants mutable access to the cells of a [Universe] through a shared reference.
+///
+/// This is what allows the parallel tick to do without locks. Users have to make sure that no
+/// cell is accessed by two threads at the same time, which is guaranteed when every thread stays
+/// within the reach of a chunk of the current phase of [Chunks].
+pub(crate) struct SharedArea<'a> {
+    /// Only used for its geometry, the cells must be accessed through [SharedArea::cells].
+    universe: &'a Universe<Particle>,
+    cells: *mut Particle,
+}
+
+// SAFETY: see the documentation of [SharedArea]
+unsafe impl Send for SharedArea<'_> {}
+unsafe impl Sync for SharedArea<'_> {}
+
+impl<'a> SharedArea<'a> {
+    pub fn new(universe: &'a mut Universe<Particle>) -> Self {
+        let cells = universe.area.as_mut_ptr();
+
+        Self { universe, cells }
+    }
+
+    pub fn universe(&self) -> &Universe<Particle> {
+        self.universe
+    }
+
+    /// Returns the cell at the given position, which has to be inside of the [Universe].
+    ///
+    /// # Safety
+    /// No other reference to the same cell may exist while the returned one is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut(&self, pos: &Position) -> &mut Particle {
+        let index = self.universe.pos_to_i(pos);
+        assert!(index < self.universe.area.len());
+
+        &mut *self.cells.add(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::universe::Boundary;
+
+    /// Returns the distance between two ranges along an axis of the given length.
+    fn gap(a: &Range<usize>, b: &Range<usize>, len: usize, wraps: bool) -> usize {
+        let direct = b
+            .start
+            .saturating_sub(a.end)
+            .max(a.start.saturating_sub(b.end));
+        if !wraps {
+            return direct;
+        }
+
+        let around = (len - a.end.max(b.end)) + a.start.min(b.start);
+        direct.min(around)
+    }
+
+    #[test]
+    fn phases_cover_every_cell_once() {
+        for boundaries in [Boundaries::default(), Boundaries::all(Boundary::Wrap)] {
+            let chunks = Chunks::new(200, 130, &boundaries);
+            let mut covered = vec![0; 200 * 130];
+            for phase in 0..4 {
+                for region in chunks.phase(phase) {
+                    for pos in region.positions_rev() {
+                        covered[pos.y * 200 + pos.x] += 1;
+                    }
+                }
+            }
+
+            assert!(covered.iter().all(|&count| count == 1));
+        }
+    }
+
+    #[test]
+    fn chunks_of_a_phase_are_out_of_reach_of_each_other() {
+        for boundaries in [Boundaries::default(), Boundaries::all(Boundary::Wrap)] {
+            let (width, height) = (200, 130);
+            let chunks = Chunks::new(width, height, &boundaries);
+
+            for phase in 0..4 {
+                let regions = chunks.phase(phase);
+                for (i, a) in regions.iter().enumerate() {
+                    for b in &regions[i + 1..] {
+                        let gap_x = gap(&a.x, &b.x, width, boundaries.wraps_x());
+                        let gap_y = gap(&a.y, &b.y, height, boundaries.wraps_y());
+                        assert!(
+                            gap_x.max(gap_y) >= CHUNK_SIZE,
+                            "{a:?} and {b:?} of phase {phase} are too close"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the awake chunks of all phases.
+    fn awake(chunks: &Chunks) -> Vec<Region> {
+        (0..4).flat_map(|phase| chunks.phase(phase)).collect()
+    }
+
+    #[test]
+    fn chunks_fall_asleep_until_they_change() {
+        let mut chunks = Chunks::new(128, 128, &Boundaries::default());
+        for _ in 0..SLEEP_DELAY {
+            assert_eq!(awake(&chunks).len(), 16);
+            chunks.settle();
+        }
+        assert_eq!(awake(&chunks).len(), 0);
+        assert_eq!(chunks.awake_positions_rev().count(), 0);
+
+        // a change at the edge of a chunk wakes up its neighbor as well
+        chunks.mark_changed(&Position { x: 31, y: 10 });
+        let regions = awake(&chunks);
+        assert_eq!(regions.len(), 2);
+        assert!(regions.iter().all(|region| region.y == (0..32)));
+        assert_eq!(chunks.awake_positions_rev().count(), 2 * 32 * 32);
+
+        chunks.settle();
+        assert_eq!(awake(&chunks).len(), 2);
+    }
+}
@@ -1,3 +1,4 @@
 pub mod cell_content;
 pub mod direction;
 pub mod material;
+pub mod registry;
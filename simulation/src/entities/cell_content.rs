@@ -1,12 +1,82 @@
+use crate::entities::direction::Direction;
 use crate::entities::material::Material;
-use crate::entities::material::Material::Air;
+use crate::heat::AMBIENT_TEMPERATURE;
+use std::ops::{Add, Mul, Sub};
 
-/// Velocity is the "speed" at which a [Material] moves through the [Universe].
-/// It is used to simulate gravity.
-pub type Velocity = i16;
+/// Velocity is the speed and direction at which a [Material] moves through the [Universe], in
+/// cells per tick. The y axis points down, like the rows of the [Universe].
+///
+/// [Universe]: crate::universe::Universe
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Velocity {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Velocity {
+    pub const ZERO: Velocity = Velocity { x: 0.0, y: 0.0 };
+
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    /// Returns a velocity of the given speed towards a [Direction].
+    pub fn along(dir: &Direction, speed: f32) -> Self {
+        let (x, y) = dir.offset();
+
+        Self::new(x as f32 * speed, y as f32 * speed)
+    }
+
+    /// Returns the number of cells per tick a particle of this velocity passes, i.e. the larger of
+    /// both components.
+    pub fn speed(&self) -> f32 {
+        self.x.abs().max(self.y.abs())
+    }
+
+    /// Returns the velocity, scaled down to the given speed if it is faster.
+    pub fn limit(self, max_speed: f32) -> Self {
+        let speed = self.speed();
+
+        if speed > max_speed {
+            self * (max_speed / speed)
+        } else {
+            self
+        }
+    }
+}
+
+impl Add for Velocity {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl Sub for Velocity {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl Mul<f32> for Velocity {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+/// Age of a [Particle] in ticks.
+pub type Age = u16;
+
+/// Temperature of a [Particle] in degrees Celsius.
+pub type Temperature = f32;
 
 /// The contents of a cell in a [Universe].
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Particle {
     /// The material properties of the cell.
     pub material: Material,
@@ -14,15 +84,28 @@ pub struct Particle {
     /// The velocity of the cell.
     pub velocity: Velocity,
 
+    /// The temperature of the cell, which is exchanged with neighboring cells.
+    pub temperature: Temperature,
+
+    /// The number of ticks the cell has existed for as its current material. Only counted for
+    /// materials that decay.
+    pub age: Age,
+
     /// Whether or not a cell has been simulated during a simulation tick of a [Simulation].
     pub handled: bool,
 }
 
 impl Particle {
+    /// Creates a particle at [AMBIENT_TEMPERATURE]. Use [MaterialRegistry::particle] to create a
+    /// particle at the initial temperature of its [Material].
+    ///
+    /// [MaterialRegistry::particle]: crate::entities::registry::MaterialRegistry::particle
     pub fn new(mat: Material, handled: bool, velocity: Velocity) -> Self {
         Self {
+            temperature: AMBIENT_TEMPERATURE,
             material: mat,
             velocity,
+            age: 0,
             handled,
         }
     }
@@ -30,6 +113,6 @@ impl Particle {
 
 impl Default for Particle {
     fn default() -> Self {
-        Particle::new(Air, false, 0)
+        Particle::new(Material::AIR, false, Velocity::ZERO)
     }
 }
@@ -1,8 +1,13 @@
+use crate::entities::direction::Direction::{
+    Down, Left, LeftDown, LeftUp, Right, RightDown, RightUp, Up,
+};
 use crate::entities::direction::ExtDirection::{One, Random};
-use rand::random;
+use crate::utils::SimRng;
+use rand::Rng;
+use serde_derive::Deserialize;
 use std::slice::Iter;
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -14,8 +19,40 @@ pub enum Direction {
     RightDown,
 }
 
+impl Direction {
+    /// Returns the offset of a neighbor in this direction, as (x, y).
+    pub fn offset(&self) -> (isize, isize) {
+        match self {
+            Up => (0, -1),
+            Down => (0, 1),
+            Left => (-1, 0),
+            Right => (1, 0),
+            LeftUp => (-1, -1),
+            RightUp => (1, -1),
+            LeftDown => (-1, 1),
+            RightDown => (1, 1),
+        }
+    }
+
+    /// Returns the direction of a neighbor at the given offset, or [None] if there is none.
+    pub fn from_offset(x: isize, y: isize) -> Option<Self> {
+        match (x.signum(), y.signum()) {
+            (0, -1) => Some(Up),
+            (0, 1) => Some(Down),
+            (-1, 0) => Some(Left),
+            (1, 0) => Some(Right),
+            (-1, -1) => Some(LeftUp),
+            (1, -1) => Some(RightUp),
+            (-1, 1) => Some(LeftDown),
+            (1, 1) => Some(RightDown),
+            _ => None,
+        }
+    }
+}
+
 /// An "extension" of the basic [Direction], indicating a specific [Direction] or a random order of multiple [Direction]s.
 #[allow(clippy::module_name_repetitions)]
+#[derive(Clone, Debug)]
 pub enum ExtDirection {
     One(Direction),
     Random(Direction, Direction),
@@ -23,16 +60,20 @@ pub enum ExtDirection {
 
 /// A sloppily implemented [Iterator] for [ExtDirection], that applies the random selection for
 /// [ExtDirection::Random] while preserving the overall order.
+///
+/// The random bits are drawn up front, so the iterator does not need to hold on to the [SimRng].
 pub struct ExtDirIterator<'a> {
     dirs: Iter<'a, ExtDirection>,
     temp_remainder: Option<&'a Direction>,
+    random_bits: u32,
 }
 
 impl<'a> ExtDirIterator<'a> {
-    pub fn new(dirs: &'a [ExtDirection]) -> Self {
+    pub fn new(dirs: &'a [ExtDirection], rng: &mut SimRng) -> Self {
         Self {
             dirs: dirs.iter(),
             temp_remainder: None,
+            random_bits: rng.gen(),
         }
     }
 }
@@ -50,7 +91,10 @@ impl<'a> Iterator for ExtDirIterator<'a> {
         match ext_dir {
             One(d) => Some(d),
             Random(a, b) => {
-                if random() {
+                let first = self.random_bits & 1 == 1;
+                self.random_bits >>= 1;
+
+                if first {
                     self.temp_remainder = Some(b);
                     Some(a)
                 } else {
@@ -1,145 +1,356 @@
-use crate::entities::direction::Direction::{
-    Down, Left, LeftDown, LeftUp, Right, RightDown, RightUp, Up,
-};
-use crate::entities::direction::ExtDirection::{One, Random};
+use crate::entities::cell_content::{Age, Temperature};
 use crate::entities::direction::{Direction, ExtDirection};
-use crate::entities::material::CollisionDesire::{
-    Consume, Convert, Eradicate, Evade, SwapAndMove, SwapAndStop,
-};
-use crate::entities::material::Material::{
-    Air, Fire, Sand, SandGenerator, Smoke, Vapor, Water, WaterGenerator, Wood,
-};
-use crate::utils;
-use rand::random;
+use crate::entities::material::Threshold::{Above, Below};
+use crate::heat::AMBIENT_TEMPERATURE;
+use rand::{Rng, RngCore};
+use serde_derive::Deserialize;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum Material {
-    Sand,
-    SandGenerator,
-    Water,
-    WaterGenerator,
-    Air,
-    Fire,
-    Smoke,
-    Vapor,
-    Wood,
-}
+/// A compact identifier of a material, whose appearance and behaviour are described by the
+/// [MaterialBehavior] it is registered with in a [MaterialRegistry].
+///
+/// The built-in materials always keep the same identifiers, which are available as constants.
+///
+/// [MaterialRegistry]: crate::entities::registry::MaterialRegistry
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Material(u16);
 
-#[allow(clippy::match_same_arms)]
 impl Material {
+    pub const SAND: Material = Material(0);
+    pub const SAND_GENERATOR: Material = Material(1);
+    pub const WATER: Material = Material(2);
+    pub const WATER_GENERATOR: Material = Material(3);
+    pub const AIR: Material = Material(4);
+    pub const FIRE: Material = Material(5);
+    pub const SMOKE: Material = Material(6);
+    pub const VAPOR: Material = Material(7);
+    pub const WOOD: Material = Material(8);
+    pub const ICE: Material = Material(9);
+    pub const OIL: Material = Material(10);
+
+    pub const fn from_id(id: u16) -> Self {
+        Self(id)
+    }
+
+    pub const fn id(self) -> u16 {
+        self.0
+    }
+}
+
+/// The appearance and behaviour of a [Material].
+///
+/// Materials are added to a simulation by registering their behaviour in a [MaterialRegistry],
+/// which assigns them a [Material] id. Besides the name, color and collisions, all properties
+/// have defaults that suit a material which neither conducts heat nor changes its phase.
+///
+/// [MaterialRegistry]: crate::entities::registry::MaterialRegistry
+pub trait MaterialBehavior: Send + Sync {
+    /// Returns the unique name of the material.
+    fn name(&self) -> &str;
+
+    /// Returns the color of the material in RGBA.
+    fn color(&self) -> [u8; 4];
+
     /// Returns the desired [Direction]s for neighbors to collide with.
-    pub(crate) fn directions(&self) -> Vec<ExtDirection> {
-        match self {
-            Sand => vec![One(Down), Random(RightDown, LeftDown)],
-            SandGenerator => vec![One(Down)],
-            Water => vec![One(Down), Random(RightDown, LeftDown), Random(Right, Left)],
-            WaterGenerator => vec![One(Down)],
-            Air => vec![],
-            Fire => vec![
-                One(Down),
-                Random(RightDown, LeftDown),
-                Random(Right, Left),
-                One(Up),
-                Random(RightUp, LeftUp),
-            ],
-            Smoke => vec![One(Up), Random(RightUp, LeftUp), Random(Right, Left)],
-            Vapor => vec![One(Up), Random(RightUp, LeftUp), Random(Right, Left)],
-            Wood => vec![],
-        }
+    fn directions(&self) -> &[ExtDirection];
+
+    /// Returns the [CollisionDesire] for a collision with a neighbor of another [Material] in the
+    /// given [Direction], or [None] if the material has no particular reaction to the other one.
+    ///
+    /// Without a reaction, the [State]s and densities of both materials decide whether the
+    /// neighbor is displaced, see [MaterialRegistry::collide].
+    ///
+    /// All randomness has to be drawn from `rng`, so that simulations stay reproducible.
+    ///
+    /// [MaterialRegistry::collide]: crate::entities::registry::MaterialRegistry::collide
+    fn collide(
+        &self,
+        other: Material,
+        dir: &Direction,
+        rng: &mut dyn RngCore,
+    ) -> Option<CollisionDesire>;
+
+    /// Returns the [State] of the material.
+    fn state(&self) -> State {
+        State::Solid
     }
 
-    /// Returns the [CollisionDesire] for a collision with another [Material].
-    pub(crate) fn collide(&self, other: &Self, dir: &Direction) -> CollisionDesire {
-        match self {
-            Sand => Self::collide_sand(other),
-            SandGenerator => Self::collide_sand_generator(other),
-            Water => Self::collide_water(other),
-            WaterGenerator => Self::collide_water_generator(other),
-            Air => Self::collide_air(other),
-            Fire => Self::collide_fire(other, dir),
-            Smoke => Self::collide_smoke(other),
-            Vapor => Self::collide_vapor(other),
-            Wood => Self::collide_wood(other),
-        }
+    /// Returns the density of the material, relative to the one of air.
+    fn density(&self) -> f32 {
+        1.0
     }
 
-    fn collide_sand(other: &Self) -> CollisionDesire {
-        match other {
-            Water => utils::rand_select(SwapAndStop, Evade),
-            Air => SwapAndMove,
-            _ => Evade,
-        }
+    /// Returns the acceleration of the material along the y axis in cells per tick², which is
+    /// negative for materials that rise.
+    fn gravity(&self) -> f32 {
+        0.0
     }
 
-    fn collide_sand_generator(other: &Self) -> CollisionDesire {
-        match other {
-            Air => {
-                if random() {
-                    Convert(Sand)
-                } else {
-                    Evade
-                }
-            }
-            _ => Evade,
-        }
+    /// Returns the highest speed particles of the material reach, in cells per tick. The speed of
+    /// all particles is also limited by the size of the chunks of a simulation.
+    fn terminal_velocity(&self) -> f32 {
+        f32::INFINITY
     }
-    fn collide_water(other: &Self) -> CollisionDesire {
-        match other {
-            Air => utils::rand_select(SwapAndMove, Evade),
-            Vapor | Smoke => utils::rand_select(SwapAndMove, Evade),
-            Fire => Eradicate(Vapor, Smoke),
-            _ => Evade,
-        }
+
+    /// Returns the temperature of newly created particles of the material.
+    fn initial_temperature(&self) -> Temperature {
+        AMBIENT_TEMPERATURE
     }
-    fn collide_water_generator(other: &Self) -> CollisionDesire {
-        match other {
-            Air => {
-                if random() {
-                    Convert(Water)
-                } else {
-                    Evade
-                }
-            }
-            _ => Evade,
-        }
+
+    /// Returns whether the material keeps its initial temperature, regardless of its surroundings.
+    fn is_heat_source(&self) -> bool {
+        false
     }
 
-    // [Air] doesn't collide with anything on its, at least not until gusts of wind or similar
-    // features are implemented
-    fn collide_air(_other: &Self) -> CollisionDesire {
-        Evade
+    /// Returns the amount of heat needed to change the temperature of the material by one degree.
+    /// Must be at least 1.
+    fn heat_capacity(&self) -> f32 {
+        1.0
     }
 
-    fn collide_fire(other: &Self, dir: &Direction) -> CollisionDesire {
-        match other {
-            Air | Smoke | Vapor => match dir {
-                Down | LeftDown | RightDown => utils::rand_select(SwapAndStop, Evade),
-                _ => Evade,
-            },
-            Water => utils::rand_select(Consume(Vapor), Eradicate(Smoke, Vapor)),
-            Wood => utils::rand_select3(Consume(Smoke), Consume(Fire), Evade),
-            _ => Evade,
-        }
+    /// Returns how well the material conducts heat, from 0 (not at all) to 1 (perfectly).
+    fn conductivity(&self) -> f32 {
+        0.0
+    }
+
+    /// Returns the [Material] a particle of this material turns into at the given temperature, or
+    /// [None] if it stays the same.
+    fn transition_at(&self, _temperature: Temperature) -> Option<Material> {
+        None
+    }
+
+    /// Returns how particles of the material decay over time, or [None] if they last forever.
+    fn decay(&self) -> Option<&Decay> {
+        None
+    }
+}
+
+/// A [MaterialBehavior] made of plain data, as loaded from a material definition file.
+#[derive(Clone, Debug)]
+pub struct MaterialDefinition {
+    pub name: String,
+
+    /// The color of the material in RGBA.
+    pub color: [u8; 4],
+
+    /// The desired [Direction]s for neighbors to collide with.
+    pub directions: Vec<ExtDirection>,
+
+    /// The [Reaction]s to neighbors, of which the first one that applies is used.
+    pub reactions: Vec<Reaction>,
+
+    pub state: State,
+
+    /// The density of the material, relative to the one of air.
+    pub density: f32,
+
+    /// The acceleration of the material along the y axis, negative for materials that rise.
+    pub gravity: f32,
+
+    /// The highest speed particles of the material reach, in cells per tick.
+    pub terminal_velocity: f32,
+
+    /// The temperature of newly created particles of the material.
+    pub initial_temperature: Temperature,
+
+    /// Whether the material keeps its initial temperature, regardless of its surroundings.
+    pub is_heat_source: bool,
+
+    /// The amount of heat needed to change the temperature of the material by one degree.
+    /// Must be at least 1.
+    pub heat_capacity: f32,
+
+    /// How well the material conducts heat, from 0 (not at all) to 1 (perfectly).
+    pub conductivity: f32,
+
+    /// The [PhaseTransition]s of the material, in the order they are checked in.
+    pub phase_transitions: Vec<PhaseTransition>,
+
+    /// How particles of the material decay over time, if at all.
+    pub decay: Option<Decay>,
+}
+
+impl MaterialBehavior for MaterialDefinition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn color(&self) -> [u8; 4] {
+        self.color
+    }
+
+    fn directions(&self) -> &[ExtDirection] {
+        &self.directions
+    }
+
+    fn collide(
+        &self,
+        other: Material,
+        dir: &Direction,
+        rng: &mut dyn RngCore,
+    ) -> Option<CollisionDesire> {
+        self.reactions
+            .iter()
+            .find(|reaction| reaction.applies_to(other, dir))
+            .map(|reaction| reaction.pick(rng))
+    }
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn density(&self) -> f32 {
+        self.density
+    }
+
+    fn gravity(&self) -> f32 {
+        self.gravity
+    }
+
+    fn terminal_velocity(&self) -> f32 {
+        self.terminal_velocity
+    }
+
+    fn initial_temperature(&self) -> Temperature {
+        self.initial_temperature
+    }
+
+    fn is_heat_source(&self) -> bool {
+        self.is_heat_source
+    }
+
+    fn heat_capacity(&self) -> f32 {
+        self.heat_capacity
+    }
+
+    fn conductivity(&self) -> f32 {
+        self.conductivity
+    }
+
+    fn transition_at(&self, temperature: Temperature) -> Option<Material> {
+        self.phase_transitions
+            .iter()
+            .find(|transition| transition.threshold.is_crossed_by(temperature))
+            .map(|transition| transition.into)
+    }
+
+    fn decay(&self) -> Option<&Decay> {
+        self.decay.as_ref()
+    }
+}
+
+/// The state of matter of a [Material], which determines how it moves and what it can displace.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum State {
+    /// Falls and piles up, but can not be displaced, e.g. sand.
+    Powder,
+    /// Flows and can be displaced by denser materials, e.g. water.
+    Liquid,
+    /// Flows and can be displaced by denser materials, e.g. air.
+    Gas,
+    /// Never moves and can not be displaced, e.g. wood.
+    #[default]
+    Solid,
+}
+
+impl State {
+    /// Returns whether the material can move on its own.
+    pub fn is_movable(self) -> bool {
+        self != State::Solid
+    }
+
+    /// Returns whether the material can be displaced by denser materials.
+    pub fn is_fluid(self) -> bool {
+        matches!(self, State::Liquid | State::Gas)
     }
-    fn collide_smoke(other: &Self) -> CollisionDesire {
-        match other {
-            // Air => rand_select(SwapAndStop, GetConverted(Air)),
-            Air => utils::rand_select(SwapAndStop, Evade),
-            Vapor => utils::rand_select(SwapAndStop, Eradicate(Water, Air)),
-            _ => Evade,
+}
+
+/// A rule for the collisions of a [Material] with its neighbors.
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    /// The materials of the neighbors the reaction applies to.
+    pub with: Vec<Material>,
+
+    /// The directions of the neighbors the reaction applies to, or all directions if empty.
+    pub directions: Vec<Direction>,
+
+    /// The possible outcomes of the reaction, each with a weight for its probability.
+    pub outcomes: Vec<(u32, CollisionDesire)>,
+}
+
+impl Reaction {
+    fn applies_to(&self, other: Material, dir: &Direction) -> bool {
+        self.with.contains(&other) && (self.directions.is_empty() || self.directions.contains(dir))
+    }
+
+    /// Picks one of the outcomes, based on their weights.
+    fn pick(&self, rng: &mut dyn RngCore) -> CollisionDesire {
+        if let [(_, outcome)] = self.outcomes.as_slice() {
+            return outcome.clone();
         }
+
+        let total: u32 = self.outcomes.iter().map(|(weight, _)| weight).sum();
+        let mut roll = rng.gen_range(0..total);
+
+        for (weight, outcome) in &self.outcomes {
+            if roll < *weight {
+                return outcome.clone();
+            }
+            roll -= weight;
+        }
+
+        unreachable!("the roll is always lower than the sum of all weights")
     }
-    fn collide_vapor(other: &Self) -> CollisionDesire {
-        // TODO: should have a way to cool down and become water again
-        match other {
-            // Air => rand_select3(SwapAndStop, GetConverted(Air), GetConverted(Water)),
-            Air => utils::rand_select(SwapAndStop, Evade),
-            Smoke => utils::rand_select(SwapAndStop, Eradicate(Air, Water)),
-            _ => Evade,
+}
+
+/// A change of a [Material] into another one, once the temperature of a particle crosses a
+/// [Threshold].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseTransition {
+    pub threshold: Threshold,
+    pub into: Material,
+}
+
+/// A temperature a [PhaseTransition] happens at.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Threshold {
+    Above(Temperature),
+    Below(Temperature),
+}
+
+impl Threshold {
+    pub fn is_crossed_by(&self, temperature: Temperature) -> bool {
+        match self {
+            Above(threshold) => temperature > *threshold,
+            Below(threshold) => temperature < *threshold,
         }
     }
-    fn collide_wood(_other: &Self) -> CollisionDesire {
-        Evade
+}
+
+/// The decay of a [Material] into another one, once a particle has reached a certain age.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decay {
+    /// The age at which particles start to decay.
+    pub after: Age,
+    /// The number of ticks by which the decay of a particle is randomly delayed at most.
+    pub jitter: Age,
+    pub into: Material,
+}
+
+impl Decay {
+    /// Returns whether a particle of the given age decays now.
+    ///
+    /// Particles decay at an age that is evenly distributed between [Decay::after] and
+    /// [Decay::after] plus [Decay::jitter].
+    pub fn is_due(&self, age: Age, rng: &mut dyn RngCore) -> bool {
+        let Some(delay) = age.checked_sub(self.after) else {
+            return false;
+        };
+
+        // of the particles that are still left, an even share decays during each remaining tick
+        match self.jitter.checked_sub(delay) {
+            Some(remaining) => rng.gen_range(0..=remaining) == 0,
+            None => true,
+        }
     }
 }
 
@@ -155,6 +366,7 @@ impl Material {
 /// [A, B] -> [B, C] // ?
 ///
 /// [A, B] -> [C, D] // Eradicate ?, e.g. [Water, Fire], [Vapor, Smoke]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum CollisionDesire {
     /// [A, B] -> [A, B] // Evade, e.g. [Sand, Wood]
     Evade,
@@ -0,0 +1,612 @@
+use crate::entities::cell_content::{Age, Particle, Temperature, Velocity};
+use crate::entities::direction::{Direction, ExtDirection};
+use crate::entities::material::CollisionDesire::{
+    Consume, Convert, Eradicate, Evade, GetConverted, SwapAndMove, SwapAndStop,
+};
+use crate::entities::material::{
+    CollisionDesire, Decay, Material, MaterialBehavior, MaterialDefinition, PhaseTransition,
+    Reaction, State, Threshold,
+};
+use crate::heat::AMBIENT_TEMPERATURE;
+use rand::RngCore;
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+use std::{fmt, fs, io};
+
+/// The definitions of the built-in materials.
+const BUILTIN_MATERIALS: &str = include_str!("../../materials.toml");
+
+/// Holds the [MaterialBehavior]s of all [Material]s a [Simulation] knows about, and assigns
+/// compact ids to them in the order they are registered in.
+///
+/// The built-in materials are always registered first, so they keep the ids of the constants of
+/// [Material]. Further materials can be registered directly through [MaterialRegistry::register],
+/// or loaded from TOML files, see `materials.toml` for the format.
+///
+/// [Simulation]: crate::sand_sim::Simulation
+#[derive(Clone)]
+pub struct MaterialRegistry {
+    behaviors: Vec<Arc<dyn MaterialBehavior>>,
+}
+
+impl MaterialRegistry {
+    /// Returns a registry of the built-in materials.
+    pub fn builtin() -> Self {
+        let mut registry = Self { behaviors: vec![] };
+        registry
+            .extend_from_toml(BUILTIN_MATERIALS)
+            .expect("the built-in materials are valid");
+
+        registry
+    }
+
+    /// Returns a registry of the built-in materials, extended by the materials of a TOML file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DefinitionError> {
+        let mut registry = Self::builtin();
+        registry.extend_from_toml(&fs::read_to_string(path)?)?;
+
+        Ok(registry)
+    }
+
+    /// Adds the materials defined in a TOML document to the registry.
+    ///
+    /// A material with the name of an already registered one replaces its definition, but keeps
+    /// its id. Materials may refer to each other, regardless of the order they are defined in.
+    /// Nothing is changed if any of the definitions is invalid.
+    pub fn extend_from_toml(&mut self, toml: &str) -> Result<(), DefinitionError> {
+        let file: MaterialFile = toml::from_str(toml)?;
+
+        // all names are known up front, so that definitions can refer to each other
+        let mut names: Vec<String> = self.names().map(str::to_string).collect();
+        for raw in &file.material {
+            if find_in(names.iter().map(String::as_str), &raw.name).is_none() {
+                names.push(raw.name.clone());
+            }
+        }
+        if names.len() > MAX_MATERIALS {
+            return Err(DefinitionError::TooManyMaterials);
+        }
+
+        let definitions = file
+            .material
+            .into_iter()
+            .map(|raw| raw.into_definition(&names))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for definition in definitions {
+            self.register(definition)?;
+        }
+
+        Ok(())
+    }
+
+    /// Registers the behaviour of a material and returns its id.
+    ///
+    /// A material with the name of an already registered one replaces its behaviour, but keeps
+    /// its id.
+    pub fn register(
+        &mut self,
+        behavior: impl MaterialBehavior + 'static,
+    ) -> Result<Material, DefinitionError> {
+        if let Some(material) = self.find(behavior.name()) {
+            self.behaviors[material.id() as usize] = Arc::new(behavior);
+            return Ok(material);
+        }
+
+        if self.behaviors.len() >= MAX_MATERIALS {
+            return Err(DefinitionError::TooManyMaterials);
+        }
+        self.behaviors.push(Arc::new(behavior));
+
+        Ok(Material::from_id((self.behaviors.len() - 1) as u16))
+    }
+
+    /// Returns the behaviour of a [Material].
+    ///
+    /// # Panics
+    /// If the material is not registered.
+    pub fn get(&self, material: Material) -> &dyn MaterialBehavior {
+        self.behaviors[material.id() as usize].as_ref()
+    }
+
+    /// Returns the [Material] with the given name, ignoring case.
+    pub fn find(&self, name: &str) -> Option<Material> {
+        find_in(self.names(), name)
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.behaviors.iter().map(|behavior| behavior.name())
+    }
+
+    /// Returns all registered materials, in the order of their ids.
+    pub fn materials(&self) -> impl Iterator<Item = (Material, &dyn MaterialBehavior)> {
+        self.behaviors
+            .iter()
+            .enumerate()
+            .map(|(id, behavior)| (Material::from_id(id as u16), behavior.as_ref()))
+    }
+
+    /// Returns the number of registered materials.
+    pub fn len(&self) -> usize {
+        self.behaviors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.behaviors.is_empty()
+    }
+
+    /// Returns the [CollisionDesire] of a [Material] for a collision with a neighbor of another
+    /// material in the given [Direction].
+    ///
+    /// Reactions of the [MaterialBehavior] take priority. Without one, a movable material
+    /// displaces a fluid neighbor if it sinks into it, i.e. it is denser and moves down or
+    /// sideways, or if it rises through it, i.e. it is lighter and moves up.
+    pub fn collide(
+        &self,
+        material: Material,
+        other: Material,
+        dir: &Direction,
+        rng: &mut dyn RngCore,
+    ) -> CollisionDesire {
+        let behavior = self.get(material);
+        if let Some(desire) = behavior.collide(other, dir, rng) {
+            return desire;
+        }
+
+        let neighbor = self.get(other);
+        if !behavior.state().is_movable() || !neighbor.state().is_fluid() {
+            return Evade;
+        }
+
+        let displaces = match dir.offset() {
+            (_, y) if y < 0 => behavior.density() < neighbor.density(),
+            _ => behavior.density() > neighbor.density(),
+        };
+
+        match (displaces, neighbor.state()) {
+            // liquids slow down whatever passes through them
+            (true, State::Liquid) => SwapAndStop,
+            (true, _) => SwapAndMove,
+            (false, _) => Evade,
+        }
+    }
+
+    /// Returns a new, unhandled [Particle] of the given [Material] at its initial temperature.
+    pub fn particle(&self, material: Material) -> Particle {
+        let mut particle = Particle::new(material, false, Velocity::ZERO);
+        particle.temperature = self.get(material).initial_temperature();
+
+        particle
+    }
+}
+
+/// The number of materials that can be told apart by the ids of [Material].
+const MAX_MATERIALS: usize = u16::MAX as usize + 1;
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+fn find_in<'a>(mut names: impl Iterator<Item = &'a str>, name: &str) -> Option<Material> {
+    names
+        .position(|candidate| candidate.eq_ignore_ascii_case(name))
+        .map(|id| Material::from_id(id as u16))
+}
+
+/// An error that occurred while registering materials or loading their definitions.
+#[derive(Debug)]
+pub enum DefinitionError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// A definition refers to a material that is not defined.
+    UnknownMaterial {
+        material: String,
+        name: String,
+    },
+    /// A definition contains an outcome that is not a valid [CollisionDesire].
+    InvalidOutcome {
+        material: String,
+        outcome: String,
+    },
+    /// An entry of the directions of a definition does not contain one or two directions.
+    InvalidDirections {
+        material: String,
+    },
+    /// A reaction of a definition has no outcome with a positive weight.
+    NoOutcomes {
+        material: String,
+    },
+    /// A phase transition of a definition has both or neither of `above` and `below`.
+    InvalidTransition {
+        material: String,
+    },
+    TooManyMaterials,
+}
+
+impl Display for DefinitionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read material definitions: {e}"),
+            Self::Parse(e) => write!(f, "failed to parse material definitions: {e}"),
+            Self::UnknownMaterial { material, name } => {
+                write!(f, "material {material} refers to unknown material {name}")
+            }
+            Self::InvalidOutcome { material, outcome } => {
+                write!(f, "material {material} has an invalid outcome: {outcome}")
+            }
+            Self::InvalidDirections { material } => write!(
+                f,
+                "material {material} has directions with other than one or two entries"
+            ),
+            Self::NoOutcomes { material } => {
+                write!(f, "material {material} has a reaction without any outcome")
+            }
+            Self::InvalidTransition { material } => write!(
+                f,
+                "material {material} has a transition without exactly one of above and below"
+            ),
+            Self::TooManyMaterials => write!(f, "too many materials"),
+        }
+    }
+}
+
+impl Error for DefinitionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DefinitionError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for DefinitionError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Parse(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct MaterialFile {
+    #[serde(default)]
+    material: Vec<RawMaterial>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMaterial {
+    name: String,
+    color: [u8; 4],
+    #[serde(default)]
+    directions: Vec<Vec<Direction>>,
+    #[serde(default)]
+    reaction: Vec<RawReaction>,
+    #[serde(default)]
+    state: State,
+    #[serde(default = "default_density")]
+    density: f32,
+    #[serde(default)]
+    gravity: f32,
+    terminal_velocity: Option<f32>,
+    #[serde(default)]
+    heat: RawHeat,
+    #[serde(default)]
+    transition: Vec<RawTransition>,
+    decay: Option<RawDecay>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawReaction {
+    with: Vec<String>,
+    #[serde(default)]
+    directions: Vec<Direction>,
+    outcomes: Vec<RawOutcome>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawOutcome {
+    outcome: String,
+    #[serde(default = "default_weight")]
+    weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_density() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawHeat {
+    initial_temperature: Temperature,
+    capacity: f32,
+    conductivity: f32,
+    source: bool,
+}
+
+impl Default for RawHeat {
+    fn default() -> Self {
+        Self {
+            initial_temperature: AMBIENT_TEMPERATURE,
+            capacity: 1.0,
+            conductivity: 0.0,
+            source: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTransition {
+    above: Option<Temperature>,
+    below: Option<Temperature>,
+    into: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDecay {
+    after: Age,
+    #[serde(default)]
+    jitter: Age,
+    into: String,
+}
+
+impl RawMaterial {
+    fn into_definition(self, names: &[String]) -> Result<MaterialDefinition, DefinitionError> {
+        let name = self.name;
+        let resolve = |other: &str| {
+            find_in(names.iter().map(String::as_str), other.trim()).ok_or_else(|| {
+                DefinitionError::UnknownMaterial {
+                    material: name.clone(),
+                    name: other.trim().to_string(),
+                }
+            })
+        };
+
+        let directions = self
+            .directions
+            .into_iter()
+            .map(|mut entry| match entry.len() {
+                1 => Ok(ExtDirection::One(entry.remove(0))),
+                2 => Ok(ExtDirection::Random(entry.remove(0), entry.remove(0))),
+                _ => Err(DefinitionError::InvalidDirections {
+                    material: name.clone(),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let reactions = self
+            .reaction
+            .into_iter()
+            .map(|raw| {
+                let outcomes: Vec<(u32, CollisionDesire)> = raw
+                    .outcomes
+                    .iter()
+                    .filter(|outcome| outcome.weight > 0)
+                    .map(|outcome| {
+                        parse_outcome(&outcome.outcome, &resolve)?.map_or_else(
+                            || {
+                                Err(DefinitionError::InvalidOutcome {
+                                    material: name.clone(),
+                                    outcome: outcome.outcome.clone(),
+                                })
+                            },
+                            |desire| Ok((outcome.weight, desire)),
+                        )
+                    })
+                    .collect::<Result<_, _>>()?;
+
+                if outcomes.is_empty() {
+                    return Err(DefinitionError::NoOutcomes {
+                        material: name.clone(),
+                    });
+                }
+
+                Ok(Reaction {
+                    with: raw
+                        .with
+                        .iter()
+                        .map(|other| resolve(other))
+                        .collect::<Result<_, _>>()?,
+                    directions: raw.directions,
+                    outcomes,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let phase_transitions = self
+            .transition
+            .into_iter()
+            .map(|raw| {
+                let threshold = match (raw.above, raw.below) {
+                    (Some(above), None) => Threshold::Above(above),
+                    (None, Some(below)) => Threshold::Below(below),
+                    _ => {
+                        return Err(DefinitionError::InvalidTransition {
+                            material: name.clone(),
+                        })
+                    }
+                };
+
+                Ok(PhaseTransition {
+                    threshold,
+                    into: resolve(&raw.into)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(MaterialDefinition {
+            color: self.color,
+            directions,
+            reactions,
+            state: self.state,
+            density: self.density,
+            gravity: self.gravity,
+            terminal_velocity: self.terminal_velocity.unwrap_or(f32::INFINITY),
+            initial_temperature: self.heat.initial_temperature,
+            is_heat_source: self.heat.source,
+            heat_capacity: self.heat.capacity.max(1.0),
+            conductivity: self.heat.conductivity.clamp(0.0, 1.0),
+            phase_transitions,
+            decay: self
+                .decay
+                .map(|raw| -> Result<_, DefinitionError> {
+                    Ok(Decay {
+                        after: raw.after,
+                        jitter: raw.jitter,
+                        into: resolve(&raw.into)?,
+                    })
+                })
+                .transpose()?,
+            name,
+        })
+    }
+}
+
+/// Parses a [CollisionDesire] written like its variant, e.g. `Eradicate(Water, Air)`.
+///
+/// Returns [None] if the outcome is malformed.
+fn parse_outcome(
+    outcome: &str,
+    resolve: &impl Fn(&str) -> Result<Material, DefinitionError>,
+) -> Result<Option<CollisionDesire>, DefinitionError> {
+    let (variant, args) = match outcome.trim().split_once('(') {
+        Some((variant, rest)) => match rest.strip_suffix(')') {
+            Some(args) => (variant.trim(), args.split(',').collect::<Vec<_>>()),
+            None => return Ok(None),
+        },
+        None => (outcome.trim(), vec![]),
+    };
+
+    let desire = match (variant, args.as_slice()) {
+        ("Evade", []) => Evade,
+        ("SwapAndMove", []) => SwapAndMove,
+        ("SwapAndStop", []) => SwapAndStop,
+        ("Convert", [mat]) => Convert(resolve(mat)?),
+        ("Consume", [mat]) => Consume(resolve(mat)?),
+        ("GetConverted", [mat]) => GetConverted(resolve(mat)?),
+        ("Eradicate", [current, neighbor]) => Eradicate(resolve(current)?, resolve(neighbor)?),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(desire))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SimRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn builtin_materials_parse() {
+        let materials = MaterialRegistry::builtin();
+
+        assert_eq!(materials.find("Sand"), Some(Material::SAND));
+        assert_eq!(materials.find("Vapor"), Some(Material::VAPOR));
+        assert_eq!(materials.find("Unobtainium"), None);
+    }
+
+    /// A solid material that turns everything but air it touches into sand.
+    struct Slime {
+        name: &'static str,
+    }
+
+    impl MaterialBehavior for Slime {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn color(&self) -> [u8; 4] {
+            [0, 255, 0, 255]
+        }
+
+        fn directions(&self) -> &[ExtDirection] {
+            &[ExtDirection::One(Direction::Down)]
+        }
+
+        fn collide(
+            &self,
+            other: Material,
+            _dir: &Direction,
+            _rng: &mut dyn RngCore,
+        ) -> Option<CollisionDesire> {
+            (other != Material::AIR).then_some(Convert(Material::SAND))
+        }
+    }
+
+    #[test]
+    fn custom_behaviors_are_registered() {
+        let mut materials = MaterialRegistry::builtin();
+        let len = materials.len();
+
+        let slime = materials.register(Slime { name: "Slime" }).unwrap();
+        assert_eq!(slime, Material::from_id(len as u16));
+        assert_eq!(materials.len(), len + 1);
+        assert_eq!(materials.find("slime"), Some(slime));
+        assert_eq!(materials.get(slime).color(), [0, 255, 0, 255]);
+
+        let mut rng = SimRng::seed_from_u64(0);
+        let desire = materials.collide(slime, Material::SAND, &Direction::Down, &mut rng);
+        assert_eq!(desire, Convert(Material::SAND));
+        let desire = materials.collide(slime, Material::AIR, &Direction::Down, &mut rng);
+        assert_eq!(desire, Evade);
+    }
+
+    #[test]
+    fn registering_a_builtin_name_replaces_its_behavior() {
+        let mut materials = MaterialRegistry::builtin();
+        let len = materials.len();
+
+        let water = materials.register(Slime { name: "water" }).unwrap();
+        assert_eq!(water, Material::WATER);
+        assert_eq!(materials.len(), len);
+        assert_eq!(materials.get(Material::WATER).name(), "water");
+        assert_eq!(materials.get(Material::WATER).color(), [0, 255, 0, 255]);
+        assert_eq!(materials.get(Material::WATER).state(), State::Solid);
+    }
+
+    #[test]
+    fn sand_does_not_sink_through_fire() {
+        let materials = MaterialRegistry::builtin();
+        let mut rng = SimRng::seed_from_u64(0);
+
+        for dir in [Direction::Down, Direction::LeftDown, Direction::RightDown] {
+            let desire = materials.collide(Material::SAND, Material::FIRE, &dir, &mut rng);
+            assert_eq!(desire, Evade);
+        }
+        let desire = materials.collide(Material::SAND, Material::AIR, &Direction::Down, &mut rng);
+        assert_eq!(desire, SwapAndMove);
+    }
+
+    #[test]
+    fn water_and_vapor_have_hysteresis() {
+        let materials = MaterialRegistry::builtin();
+        let water = materials.get(Material::WATER);
+        let vapor = materials.get(Material::VAPOR);
+
+        // between both thresholds, neither water nor vapor turn into the other
+        assert_eq!(water.transition_at(97.0), None);
+        assert_eq!(vapor.transition_at(97.0), None);
+
+        assert_eq!(water.transition_at(101.0), Some(Material::VAPOR));
+        assert_eq!(vapor.transition_at(94.0), Some(Material::WATER));
+    }
+}
@@ -0,0 +1,77 @@
+//! Exports worlds as images, the counterpart of [import](crate::import).
+
+use crate::entities::registry::MaterialRegistry;
+use crate::sand_sim::Cell;
+use crate::universe::Universe;
+use png::{BitDepth, ColorType};
+use std::io;
+use std::io::Write;
+
+/// Writes a [Universe] as RGBA PNG image, with each cell drawn in the colour of its material as
+/// drawn by the renderers.
+///
+/// The image can be read again with [read_png] and a [Palette] of the same materials, unless
+/// materials share a colour.
+///
+/// [read_png]: crate::import::read_png
+/// [Palette]: crate::import::Palette
+pub fn write_png(
+    writer: impl Write,
+    universe: &Universe<Cell>,
+    materials: &MaterialRegistry,
+) -> io::Result<()> {
+    let (Ok(width), Ok(height)) = (
+        u32::try_from(universe.width),
+        u32::try_from(universe.height),
+    ) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "universe is too large to be stored in an image",
+        ));
+    };
+
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut data = Vec::with_capacity(universe.area.len() * 4);
+    for cell in &universe.area {
+        data.extend_from_slice(&materials.get(cell.material).color());
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::material::Material;
+    use crate::import::{read_png, Palette};
+    use crate::sand_sim::Simulation;
+
+    #[test]
+    fn exported_images_are_imported_as_the_same_materials() {
+        let materials = [
+            Material::AIR,
+            Material::SAND,
+            Material::WATER,
+            Material::WOOD,
+            Material::FIRE,
+        ];
+        let area: Vec<_> = (0..7 * 5).map(|i| materials[i % materials.len()]).collect();
+        let mut sim = Simulation::with_seed(7, 5, 0);
+        sim.fill(&area);
+
+        let mut bytes = Vec::new();
+        write_png(&mut bytes, sim.universe(), sim.materials()).unwrap();
+        let palette = Palette::from_materials(sim.materials());
+        let scene = read_png(bytes.as_slice(), &palette).unwrap();
+
+        assert_eq!((scene.width, scene.height), (7, 5));
+        assert_eq!(scene.area, area);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::chunks::{Region, SharedArea};
+use crate::entities::cell_content::{Particle, Temperature};
+use crate::entities::direction::Direction;
+use crate::entities::registry::MaterialRegistry;
+use crate::universe::{Position, Universe};
+
+/// The temperature of the surroundings of a [Universe], which particles slowly cool down or heat
+/// up to.
+pub(crate) const AMBIENT_TEMPERATURE: Temperature = 20.0;
+
+/// The share of a temperature difference between two neighbors that is exchanged per tick, given
+/// both are perfect conductors.
+///
+/// Must not be higher than 0.5, as neighbors would otherwise overshoot each other's temperature.
+const CONDUCTION_RATE: f32 = 0.25;
+
+/// The share of the difference to [AMBIENT_TEMPERATURE] that each particle loses per tick.
+///
+/// Without it, heat sources would eventually heat up the whole [Universe].
+const AMBIENT_EXCHANGE_RATE: f32 = 0.002;
+
+/// Temperature changes below this threshold do not keep a chunk from falling asleep.
+const NOTICEABLE_CHANGE: Temperature = 0.05;
+
+/// Conducts heat between all neighboring cells within a [Region], as well as between its cells and
+/// the cells right next to its right and bottom edges.
+///
+/// Calls `on_change` with the positions of cells whose temperature changed noticeably.
+pub(crate) fn conduct(
+    area: &SharedArea,
+    materials: &MaterialRegistry,
+    region: &Region,
+    mut on_change: impl FnMut(&Position),
+) {
+    let universe = area.universe();
+
+    for pos in region.positions_rev() {
+        // SAFETY: only the cells of the region and their direct neighbors are accessed, which is
+        // safe for the chunks of a phase
+        let cell = unsafe { area.get_mut(&pos) };
+        let mut change = cool_down(materials, cell);
+
+        for dir in [Direction::Right, Direction::Down] {
+            let Some(neighbor_pos) = universe.get_neighbor_pos(&pos, &dir) else {
+                continue;
+            };
+            // SAFETY: see above
+            let neighbor = unsafe { area.get_mut(&neighbor_pos) };
+            let neighbor_change = exchange(materials, cell, neighbor);
+
+            if neighbor_change >= NOTICEABLE_CHANGE {
+                on_change(&neighbor_pos);
+            }
+            change = change.max(neighbor_change);
+        }
+
+        if change >= NOTICEABLE_CHANGE {
+            on_change(&pos);
+        }
+    }
+}
+
+/// Conducts heat between pairs of neighboring cells, e.g. across the edge between a part of a
+/// [Universe] that is simulated and one that is not.
+///
+/// Calls `on_change` with the positions of cells whose temperature changed noticeably.
+pub(crate) fn conduct_between(
+    universe: &mut Universe<Particle>,
+    materials: &MaterialRegistry,
+    pairs: impl IntoIterator<Item = (Position, Position)>,
+    mut on_change: impl FnMut(&Position),
+) {
+    for (a, b) in pairs {
+        let (i, j) = (universe.pos_to_i(&a), universe.pos_to_i(&b));
+        let (low, high) = universe.area.split_at_mut(i.max(j));
+        let change = exchange(materials, &mut low[i.min(j)], &mut high[0]);
+
+        if change >= NOTICEABLE_CHANGE {
+            on_change(&a);
+            on_change(&b);
+        }
+    }
+}
+
+/// Exchanges heat with the surroundings of the [Universe] and returns the change in temperature.
+///
+/// Heat sources are kept at their initial temperature instead.
+fn cool_down(materials: &MaterialRegistry, cell: &mut Particle) -> Temperature {
+    let definition = materials.get(cell.material);
+    let target = if definition.is_heat_source() {
+        definition.initial_temperature()
+    } else {
+        cell.temperature + (AMBIENT_TEMPERATURE - cell.temperature) * AMBIENT_EXCHANGE_RATE
+    };
+
+    let delta = target - cell.temperature;
+    cell.temperature += delta;
+
+    delta.abs()
+}
+
+/// Exchanges heat between two neighboring cells and returns the larger change in temperature.
+///
+/// Heat sources keep their temperature, the temperature of other cells changes inversely
+/// proportional to their heat capacity.
+fn exchange(materials: &MaterialRegistry, a: &mut Particle, b: &mut Particle) -> Temperature {
+    let (def_a, def_b) = (materials.get(a.material), materials.get(b.material));
+    let conductivity = def_a.conductivity().min(def_b.conductivity());
+    let energy = (b.temperature - a.temperature) * conductivity * CONDUCTION_RATE;
+
+    let delta_a = if def_a.is_heat_source() {
+        0.0
+    } else {
+        energy / def_a.heat_capacity()
+    };
+    let delta_b = if def_b.is_heat_source() {
+        0.0
+    } else {
+        -energy / def_b.heat_capacity()
+    };
+
+    a.temperature += delta_a;
+    b.temperature += delta_b;
+
+    delta_a.abs().max(delta_b.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::material::Material;
+
+    fn particle(materials: &MaterialRegistry, material: Material, temperature: f32) -> Particle {
+        let mut particle = materials.particle(material);
+        particle.temperature = temperature;
+        particle
+    }
+
+    #[test]
+    fn exchanges_conserve_heat() {
+        let materials = MaterialRegistry::builtin();
+        let mut water = particle(&materials, Material::WATER, 80.0);
+        let mut ice = particle(&materials, Material::ICE, -20.0);
+        let heat = |water: &Particle, ice: &Particle| {
+            water.temperature * materials.get(Material::WATER).heat_capacity()
+                + ice.temperature * materials.get(Material::ICE).heat_capacity()
+        };
+        let before = heat(&water, &ice);
+
+        for _ in 0..100 {
+            exchange(&materials, &mut water, &mut ice);
+            // the colder particle never ends up warmer than the other one
+            assert!(ice.temperature <= water.temperature);
+        }
+
+        assert!((heat(&water, &ice) - before).abs() < 1e-2);
+        assert!(water.temperature - ice.temperature < 50.0);
+    }
+
+    #[test]
+    fn heat_sources_keep_their_temperature() {
+        let materials = MaterialRegistry::builtin();
+        let initial = materials.get(Material::FIRE).initial_temperature();
+        let mut fire = particle(&materials, Material::FIRE, initial);
+        let mut wood = particle(&materials, Material::WOOD, 20.0);
+
+        exchange(&materials, &mut fire, &mut wood);
+        cool_down(&materials, &mut fire);
+
+        assert_eq!(fire.temperature, initial);
+        assert!(wood.temperature > 20.0);
+    }
+
+    #[test]
+    fn particles_cool_down_to_the_ambient_temperature() {
+        let materials = MaterialRegistry::builtin();
+        let mut hot = particle(&materials, Material::SAND, 500.0);
+        let mut cold = particle(&materials, Material::SAND, -100.0);
+
+        cool_down(&materials, &mut hot);
+        cool_down(&materials, &mut cold);
+
+        assert!(hot.temperature < 500.0 && hot.temperature > AMBIENT_TEMPERATURE);
+        assert!(cold.temperature > -100.0 && cold.temperature < AMBIENT_TEMPERATURE);
+    }
+}
@@ -0,0 +1,361 @@
+//! Keeps track of the changes to a [Universe], so that they can be undone and redone, see
+//! [Simulation::undo].
+//!
+//! [Universe]: crate::universe::Universe
+//! [Simulation::undo]: crate::sand_sim::Simulation::undo
+
+use crate::sand_sim::Cell;
+use std::collections::{HashMap, VecDeque};
+
+/// A bounded history of changes to the cells of a simulation.
+///
+/// Edits through [Simulation::get_cell_mut] and the fill methods are collected until they are
+/// committed with [Simulation::commit_edit], so that e.g. a whole brush stroke can be undone at
+/// once. Optionally, the changes of every tick are recorded as well, which requires a copy of the
+/// universe per tick.
+///
+/// Once the history is full, the oldest changes are dropped.
+///
+/// [Simulation::get_cell_mut]: crate::sand_sim::Simulation::get_cell_mut
+/// [Simulation::commit_edit]: crate::sand_sim::Simulation::commit_edit
+#[derive(Debug, Default)]
+pub struct History {
+    capacity: usize,
+    record_ticks: bool,
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+    /// The contents of the cells edited since the last call of [History::settle], by index.
+    pending: HashMap<usize, Cell>,
+    /// The changes of all edits since the last commit, by index.
+    edit: HashMap<usize, CellChange>,
+}
+
+/// The changes to a set of cells, which are undone and redone together.
+#[derive(Debug)]
+struct Change {
+    cells: Vec<CellChange>,
+}
+
+#[derive(Debug)]
+struct CellChange {
+    index: usize,
+    before: Cell,
+    after: Cell,
+}
+
+impl History {
+    /// Creates a history of up to `capacity` changes, which includes the changes of ticks if
+    /// `record_ticks` is set. A capacity of zero disables the history.
+    ///
+    /// Without recording ticks, an undo can only revert the cells of an edit that no tick has
+    /// changed since. Cells that have changed in the meantime, e.g. because painted sand has sunk
+    /// into water, are left as they are, so that particles are neither duplicated nor lost.
+    pub fn new(capacity: usize, record_ticks: bool) -> Self {
+        Self {
+            capacity,
+            record_ticks,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the number of changes the history keeps.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Returns whether the changes of ticks are recorded.
+    pub fn records_ticks(&self) -> bool {
+        self.is_enabled() && self.record_ticks
+    }
+
+    /// Returns whether there is a change to undo, including uncommitted edits.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || !self.pending.is_empty() || !self.edit.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Remembers the content of a cell before it is edited.
+    pub(crate) fn record_edit(&mut self, index: usize, cell: &Cell) {
+        if self.is_enabled() {
+            self.pending.entry(index).or_insert_with(|| cell.clone());
+        }
+    }
+
+    /// Takes note of the results of the edits since the last call, before a tick moves them away.
+    pub(crate) fn settle(&mut self, area: &[Cell]) {
+        for (index, before) in self.pending.drain() {
+            let after = area[index].clone();
+            self.edit
+                .entry(index)
+                .and_modify(|change| change.after = after.clone())
+                .or_insert(CellChange {
+                    index,
+                    before,
+                    after,
+                });
+        }
+    }
+
+    /// Turns all edits since the last commit into a single change.
+    pub(crate) fn commit(&mut self, area: &[Cell]) {
+        self.settle(area);
+
+        let mut cells: Vec<_> = self
+            .edit
+            .drain()
+            .map(|(_, change)| change)
+            .filter(|change| !same_state(&change.before, &change.after))
+            .collect();
+        cells.sort_unstable_by_key(|change| change.index);
+
+        self.push(Change { cells });
+    }
+
+    /// Records the changes of a tick, given the area before and after the tick.
+    pub(crate) fn record_tick(&mut self, before: Vec<Cell>, after: &[Cell]) {
+        let cells = before
+            .into_iter()
+            .zip(after)
+            .enumerate()
+            .filter(|(_, (before, after))| !same_state(before, after))
+            .map(|(index, (before, after))| CellChange {
+                index,
+                before,
+                after: after.clone(),
+            })
+            .collect();
+
+        self.push(Change { cells });
+    }
+
+    fn push(&mut self, change: Change) {
+        if change.cells.is_empty() || !self.is_enabled() {
+            return;
+        }
+
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(change);
+        self.redo.clear();
+    }
+
+    /// Reverts the latest change and calls `on_change` with the index of every reverted cell.
+    /// Returns whether there was a change to undo.
+    pub(crate) fn undo(&mut self, area: &mut [Cell], on_change: impl FnMut(usize)) -> bool {
+        self.commit(area);
+
+        let Some(change) = self.undo.pop_back() else {
+            return false;
+        };
+        let change = change.apply(area, |cell| &cell.after, |cell| &cell.before, on_change);
+        if !change.cells.is_empty() {
+            self.redo.push(change);
+        }
+
+        true
+    }
+
+    /// Restores the latest undone change and calls `on_change` with the index of every restored
+    /// cell. Returns whether there was a change to redo.
+    pub(crate) fn redo(&mut self, area: &mut [Cell], on_change: impl FnMut(usize)) -> bool {
+        let Some(change) = self.redo.pop() else {
+            return false;
+        };
+        let change = change.apply(area, |cell| &cell.before, |cell| &cell.after, on_change);
+        if !change.cells.is_empty() {
+            self.undo.push_back(change);
+        }
+
+        true
+    }
+}
+
+impl Change {
+    /// Replaces the `from` content of every cell with its `to` content, and returns the change of
+    /// the replaced cells. Cells that no longer hold their `from` content have been changed by ticks
+    /// that were not recorded, and are skipped.
+    fn apply(
+        self,
+        area: &mut [Cell],
+        from: impl Fn(&CellChange) -> &Cell,
+        to: impl Fn(&CellChange) -> &Cell,
+        mut on_change: impl FnMut(usize),
+    ) -> Self {
+        let mut cells = self.cells;
+        cells.retain(|cell| {
+            let cell_content = &mut area[cell.index];
+            if !same_state(cell_content, from(cell)) {
+                return false;
+            }
+            cell_content.clone_from(to(cell));
+            on_change(cell.index);
+            true
+        });
+
+        Self { cells }
+    }
+}
+
+/// Returns whether two cells have the same state, ignoring whether they have been handled, which
+/// only matters during a tick.
+fn same_state(a: &Cell, b: &Cell) -> bool {
+    a.material == b.material
+        && a.velocity == b.velocity
+        && a.temperature == b.temperature
+        && a.age == b.age
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::material::Material;
+    use crate::sand_sim::Simulation;
+    use crate::universe::Position;
+
+    fn simulation(history: History) -> Simulation {
+        let mut sim = Simulation::with_seed(8, 8, 0);
+        sim.set_history(history);
+        sim
+    }
+
+    fn materials(sim: &Simulation) -> Vec<Material> {
+        sim.universe()
+            .area
+            .iter()
+            .map(|cell| cell.material)
+            .collect()
+    }
+
+    fn count(sim: &Simulation, material: Material) -> usize {
+        materials(sim).iter().filter(|&&m| m == material).count()
+    }
+
+    fn paint(sim: &mut Simulation, x: usize, material: Material) {
+        let particle = sim.materials().particle(material);
+        *sim.get_cell_mut(&Position { x, y: 0 }).unwrap() = particle;
+    }
+
+    #[test]
+    fn committed_edits_are_undone_and_redone_at_once() {
+        let mut sim = simulation(History::new(10, false));
+        let empty = materials(&sim);
+
+        paint(&mut sim, 1, Material::WOOD);
+        paint(&mut sim, 2, Material::WOOD);
+        sim.commit_edit();
+        let painted = materials(&sim);
+
+        assert!(sim.undo());
+        assert_eq!(materials(&sim), empty);
+        assert!(!sim.undo());
+
+        assert!(sim.redo());
+        assert_eq!(materials(&sim), painted);
+        assert!(!sim.redo());
+    }
+
+    #[test]
+    fn ticks_are_undone_if_recorded() {
+        let mut sim = simulation(History::new(10, true));
+        paint(&mut sim, 3, Material::SAND);
+        let mut states = vec![materials(&sim)];
+        for _ in 0..3 {
+            sim.tick();
+            states.push(materials(&sim));
+        }
+
+        // the pending edit is committed before the first tick
+        for state in states.iter().rev().skip(1) {
+            assert!(sim.undo());
+            assert_eq!(&materials(&sim), state);
+        }
+        assert!(sim.undo());
+        assert!(!sim.history().can_undo());
+
+        for state in &states {
+            assert!(sim.redo());
+            assert_eq!(&materials(&sim), state);
+        }
+    }
+
+    #[test]
+    fn cells_moved_by_unrecorded_ticks_are_not_undone() {
+        let mut sim = simulation(History::new(10, false));
+        sim.fill(&[Material::WATER; 8 * 8]);
+        sim.commit_edit();
+
+        paint(&mut sim, 3, Material::SAND);
+        paint(&mut sim, 5, Material::WOOD);
+        sim.commit_edit();
+        for _ in 0..5 {
+            sim.tick();
+        }
+        let sand = Position { x: 3, y: 0 };
+        assert_ne!(
+            sim.universe().get_cell(&sand).unwrap().material,
+            Material::SAND
+        );
+
+        // the wood has stayed in place and is reverted, the sand has sunk and is kept
+        assert!(sim.undo());
+        assert_eq!(count(&sim, Material::WOOD), 0);
+        assert_eq!(count(&sim, Material::SAND), 1);
+        assert_eq!(count(&sim, Material::WATER), 8 * 8 - 1);
+
+        assert!(sim.redo());
+        assert_eq!(count(&sim, Material::WOOD), 1);
+        assert_eq!(count(&sim, Material::SAND), 1);
+        assert_eq!(count(&sim, Material::WATER), 8 * 8 - 2);
+    }
+
+    #[test]
+    fn the_oldest_changes_are_dropped() {
+        let mut sim = simulation(History::new(2, false));
+        for x in 0..3 {
+            paint(&mut sim, x, Material::WOOD);
+            sim.commit_edit();
+        }
+
+        assert!(sim.undo());
+        assert!(sim.undo());
+        assert!(!sim.undo());
+        assert_eq!(
+            sim.universe()
+                .get_cell(&Position { x: 0, y: 0 })
+                .unwrap()
+                .material,
+            Material::WOOD
+        );
+    }
+
+    #[test]
+    fn new_edits_discard_undone_changes() {
+        let mut sim = simulation(History::new(10, false));
+        paint(&mut sim, 0, Material::WOOD);
+        sim.commit_edit();
+        assert!(sim.undo());
+
+        paint(&mut sim, 1, Material::WOOD);
+        sim.commit_edit();
+        assert!(!sim.history().can_redo());
+    }
+
+    #[test]
+    fn a_disabled_history_records_nothing() {
+        let mut sim = simulation(History::default());
+        paint(&mut sim, 0, Material::WOOD);
+        sim.commit_edit();
+        sim.tick();
+
+        assert!(!sim.history().can_undo());
+        assert!(!sim.undo());
+    }
+}
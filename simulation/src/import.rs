@@ -0,0 +1,258 @@
+//! Imports worlds from images, so that scenes can be drawn in any pixel-art editor.
+
+use crate::entities::material::Material;
+use crate::entities::registry::MaterialRegistry;
+use crate::universe::{Boundaries, Position, Universe};
+use png::{ColorType, Transformations};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+/// An RGBA colour.
+pub type Color = [u8; 4];
+
+/// Maps the colours of an image to [Material]s.
+///
+/// Fully transparent colours all map to the same [Material], regardless of their RGB values.
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    colors: HashMap<Color, Material>,
+}
+
+impl Palette {
+    /// Creates an empty palette.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a palette of the colours of all materials in a [MaterialRegistry], as drawn by the
+    /// renderers. If materials share a colour, the one registered first is used.
+    pub fn from_materials(materials: &MaterialRegistry) -> Self {
+        let mut palette = Self::new();
+        for (material, behavior) in materials.materials() {
+            palette
+                .colors
+                .entry(Self::key(behavior.color()))
+                .or_insert(material);
+        }
+
+        palette
+    }
+
+    /// Maps a colour to a [Material] and returns the [Material] it was mapped to before, if any.
+    pub fn insert(&mut self, color: Color, material: Material) -> Option<Material> {
+        self.colors.insert(Self::key(color), material)
+    }
+
+    /// Returns the [Material] a colour is mapped to.
+    pub fn get(&self, color: Color) -> Option<Material> {
+        self.colors.get(&Self::key(color)).copied()
+    }
+
+    fn key(color: Color) -> Color {
+        match color {
+            [_, _, _, 0] => [0; 4],
+            color => color,
+        }
+    }
+}
+
+/// Reads a PNG image and maps each of its pixels to a [Material] through the given [Palette].
+///
+/// The returned [Universe] has the size of the image, so its area can be passed on to
+/// [Simulation::fill] of a simulation of the same size.
+///
+/// [Simulation::fill]: crate::sand_sim::Simulation::fill
+pub fn read_png(reader: impl Read, palette: &Palette) -> Result<Universe<Material>, ImportError> {
+    let mut decoder = png::Decoder::new(reader);
+    // resolves indexed images and low bit depths, so that we only have to handle 8 bit channels
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let (width, height) = (info.width as usize, info.height as usize);
+
+    let channels = info.color_type.samples();
+    let mut area = Vec::with_capacity(width * height);
+    let mut unknown = Vec::new();
+
+    for (i, pixel) in buf[..info.buffer_size()].chunks_exact(channels).enumerate() {
+        let color = to_rgba(info.color_type, pixel);
+
+        match palette.get(color) {
+            Some(material) => area.push(material),
+            None => {
+                area.push(Material::AIR);
+                unknown.push(UnknownColor {
+                    pos: Position::new(i % width, i / width),
+                    color,
+                });
+            }
+        }
+    }
+
+    if !unknown.is_empty() {
+        return Err(ImportError::UnknownColors(unknown));
+    }
+
+    Ok(Universe {
+        area,
+        width,
+        height,
+        boundaries: Boundaries::default(),
+    })
+}
+
+fn to_rgba(color_type: ColorType, pixel: &[u8]) -> Color {
+    match (color_type, pixel) {
+        (ColorType::Grayscale, &[v]) => [v, v, v, 0xff],
+        (ColorType::GrayscaleAlpha, &[v, a]) => [v, v, v, a],
+        (ColorType::Rgb, &[r, g, b]) => [r, g, b, 0xff],
+        (ColorType::Rgba, &[r, g, b, a]) => [r, g, b, a],
+        _ => unreachable!("indexed images are expanded by the decoder"),
+    }
+}
+
+/// A pixel whose colour is not part of the [Palette].
+#[derive(Clone, Debug)]
+pub struct UnknownColor {
+    pub pos: Position,
+    pub color: Color,
+}
+
+/// An error that occurred while importing an image.
+#[derive(Debug)]
+pub enum ImportError {
+    Decoding(png::DecodingError),
+    /// Pixels of the image have colours that are not part of the [Palette].
+    UnknownColors(Vec<UnknownColor>),
+}
+
+/// The number of [UnknownColor]s listed when displaying an [ImportError].
+const DISPLAYED_UNKNOWN_COLORS: usize = 10;
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decoding(e) => write!(f, "failed to decode image: {e}"),
+            Self::UnknownColors(unknown) => {
+                write!(
+                    f,
+                    "image contains unknown colours at {} pixels:",
+                    unknown.len()
+                )?;
+                for UnknownColor { pos, color } in unknown.iter().take(DISPLAYED_UNKNOWN_COLORS) {
+                    let [r, g, b, a] = color;
+                    write!(
+                        f,
+                        "\n  ({}, {}): #{r:02x}{g:02x}{b:02x}{a:02x}",
+                        pos.x, pos.y
+                    )?;
+                }
+                if unknown.len() > DISPLAYED_UNKNOWN_COLORS {
+                    write!(
+                        f,
+                        "\n  and {} more",
+                        unknown.len() - DISPLAYED_UNKNOWN_COLORS
+                    )?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Decoding(e) => Some(e),
+            Self::UnknownColors(_) => None,
+        }
+    }
+}
+
+impl From<png::DecodingError> for ImportError {
+    fn from(e: png::DecodingError) -> Self {
+        Self::Decoding(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes RGBA pixels as PNG image of the given width.
+    fn png(width: u32, pixels: &[Color]) -> Vec<u8> {
+        let height = pixels.len() as u32 / width;
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels.concat()).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn pixels_are_mapped_to_materials() {
+        let materials = MaterialRegistry::builtin();
+        let color = |material| materials.get(material).color();
+        let mut palette = Palette::from_materials(&materials);
+        palette.insert([0, 0, 0, 0], Material::AIR);
+        let pixels = [
+            color(Material::SAND),
+            color(Material::WATER),
+            [0x12, 0x34, 0x56, 0],
+            color(Material::WOOD),
+            color(Material::FIRE),
+            color(Material::SAND),
+        ];
+
+        let scene = read_png(png(3, &pixels).as_slice(), &palette).unwrap();
+
+        assert_eq!((scene.width, scene.height), (3, 2));
+        assert_eq!(
+            scene.area,
+            [
+                Material::SAND,
+                Material::WATER,
+                Material::AIR,
+                Material::WOOD,
+                Material::FIRE,
+                Material::SAND,
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_colours_are_reported_with_their_positions() {
+        let mut palette = Palette::new();
+        palette.insert([0xff, 0, 0, 0xff], Material::FIRE);
+        let pixels = [
+            [0xff, 0, 0, 0xff],
+            [0, 0xff, 0, 0xff],
+            [0xff, 0, 0, 0xff],
+            [0, 0, 0xff, 0x80],
+        ];
+
+        let result = read_png(png(2, &pixels).as_slice(), &palette);
+
+        let Err(ImportError::UnknownColors(unknown)) = result else {
+            panic!("unknown colours were accepted");
+        };
+        let unknown: Vec<_> = unknown.iter().map(|u| (u.pos.clone(), u.color)).collect();
+        assert_eq!(
+            unknown,
+            [
+                (Position::new(1, 0), [0, 0xff, 0, 0xff]),
+                (Position::new(1, 1), [0, 0, 0xff, 0x80]),
+            ]
+        );
+    }
+}
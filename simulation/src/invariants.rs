@@ -0,0 +1,428 @@
+//! Invariants that have to hold after every tick of a [Simulation], which can be checked to find
+//! particles that are lost, duplicated or simulated more than once, see
+//! [Simulation::set_check_invariants].
+//!
+//! [Simulation]: crate::sand_sim::Simulation
+//! [Simulation::set_check_invariants]: crate::sand_sim::Simulation::set_check_invariants
+
+use crate::chunks::{Region, MAX_VELOCITY};
+use crate::entities::cell_content::Velocity;
+use crate::entities::material::CollisionDesire::{
+    Consume, Convert, Eradicate, Evade, GetConverted, SwapAndMove, SwapAndStop,
+};
+use crate::entities::material::{CollisionDesire, Material};
+use crate::sand_sim::Cell;
+use crate::universe::{Position, Universe};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::ops::AddAssign;
+
+/// How much faster than [MAX_VELOCITY] a particle may be, to allow for rounding errors.
+const VELOCITY_TOLERANCE: f32 = 1e-3;
+
+/// An invariant that did not hold after a tick.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// The number of the tick, see [Simulation::ticks].
+    ///
+    /// [Simulation::ticks]: crate::sand_sim::Simulation::ticks
+    pub tick: u64,
+    pub kind: ViolationKind,
+}
+
+/// The invariants that are checked after each tick.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViolationKind {
+    /// The number of particles of a material changed by more or less than the reactions, decays,
+    /// transitions and voided particles of the tick explain.
+    MaterialCount {
+        material: Material,
+        expected: isize,
+        actual: usize,
+    },
+    /// A particle in an awake chunk was neither simulated nor displaced by another particle.
+    Unhandled { pos: Position, material: Material },
+    /// A cell of an awake chunk was never visited by the steppers.
+    Unscanned { pos: Position },
+    /// A cell was visited by the steppers more than once.
+    ScannedTwice { pos: Position },
+    /// The particles that were simulated, plus the ones that were displaced or replaced before
+    /// they were simulated, do not add up to the cells that are marked as handled afterwards.
+    /// Either particles were simulated more than once, or marked as handled without either.
+    HandledCount {
+        simulated: usize,
+        displaced: usize,
+        handled: usize,
+    },
+    /// A particle is faster than [MAX_VELOCITY], or its velocity is not finite.
+    Velocity {
+        pos: Position,
+        material: Material,
+        velocity: Velocity,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "tick {}: ", self.tick)?;
+
+        match &self.kind {
+            ViolationKind::MaterialCount {
+                material,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "expected {expected} particles of material {}, found {actual}",
+                material.id()
+            ),
+            ViolationKind::Unhandled { pos, material } => write!(
+                f,
+                "particle of material {} at ({}, {}) was not handled",
+                material.id(),
+                pos.x,
+                pos.y
+            ),
+            ViolationKind::Unscanned { pos } => {
+                write!(f, "cell at ({}, {}) was not scanned", pos.x, pos.y)
+            }
+            ViolationKind::ScannedTwice { pos } => {
+                write!(
+                    f,
+                    "cell at ({}, {}) was scanned more than once",
+                    pos.x, pos.y
+                )
+            }
+            ViolationKind::HandledCount {
+                simulated,
+                displaced,
+                handled,
+            } => write!(
+                f,
+                "{simulated} particles were simulated and {displaced} displaced, but {handled} \
+                 cells are marked as handled"
+            ),
+            ViolationKind::Velocity {
+                pos,
+                material,
+                velocity,
+            } => write!(
+                f,
+                "particle of material {} at ({}, {}) has velocity ({}, {})",
+                material.id(),
+                pos.x,
+                pos.y,
+                velocity.x,
+                velocity.y
+            ),
+        }
+    }
+}
+
+/// What the steppers of a tick recorded to check the invariants afterwards.
+#[derive(Debug, Default)]
+pub(crate) struct Ledger {
+    /// The expected change of the number of particles of each [Material], indexed by the id of
+    /// the material.
+    changes: Vec<isize>,
+    /// The positions of the cells the steppers visited, in order.
+    scanned: Vec<Position>,
+    /// The number of particles that were simulated.
+    simulated: usize,
+    /// The number of particles that were displaced or replaced by another particle before they
+    /// were simulated.
+    displaced: usize,
+}
+
+impl Ledger {
+    /// Records that a stepper visited the cell at the given [Position].
+    pub fn scan(&mut self, pos: &Position) {
+        self.scanned.push(pos.clone());
+    }
+
+    /// Records that a particle was simulated.
+    pub fn simulate(&mut self) {
+        self.simulated += 1;
+    }
+
+    /// Records that a particle was displaced or replaced by another particle before it was
+    /// simulated.
+    pub fn displace(&mut self) {
+        self.displaced += 1;
+    }
+
+    /// Records that a particle changed its [Material], e.g. because it decayed.
+    pub fn convert(&mut self, from: Material, to: Material) {
+        if from == to {
+            return;
+        }
+
+        let len = usize::from(from.id().max(to.id())) + 1;
+        if self.changes.len() < len {
+            self.changes.resize(len, 0);
+        }
+        self.changes[usize::from(from.id())] -= 1;
+        self.changes[usize::from(to.id())] += 1;
+    }
+
+    /// Records the outcome of a collision between a particle and its neighbor, which were made
+    /// of the given materials before.
+    ///
+    /// The changes are derived from the [CollisionDesire] alone, so that a collision that is not
+    /// carried out as described shows up as wrong number of particles.
+    pub fn collide(&mut self, before: (Material, Material), desire: &CollisionDesire) {
+        let (cell, neighbor) = before;
+        let after = match *desire {
+            Evade | SwapAndMove | SwapAndStop => return,
+            Convert(material) => (cell, material),
+            Consume(material) => (material, cell),
+            GetConverted(material) => (material, neighbor),
+            Eradicate(a, b) => (a, b),
+        };
+
+        // the materials of both cells are exchanged at once, so that swaps cancel out
+        for (from, to) in [(cell, after.0), (neighbor, after.1)] {
+            self.convert(from, to);
+        }
+    }
+}
+
+impl AddAssign for Ledger {
+    fn add_assign(&mut self, rhs: Self) {
+        if self.changes.len() < rhs.changes.len() {
+            self.changes.resize(rhs.changes.len(), 0);
+        }
+        for (change, other) in self.changes.iter_mut().zip(rhs.changes) {
+            *change += other;
+        }
+        self.scanned.extend(rhs.scanned);
+        self.simulated += rhs.simulated;
+        self.displaced += rhs.displaced;
+    }
+}
+
+/// The state of a [Universe] at the start of a tick, which the invariants are checked against.
+///
+/// All cells are unhandled at the start of a tick. Every cell of an awake chunk has to be scanned
+/// exactly once, and every particle has to be handled exactly once: either it is simulated, or it
+/// is displaced or replaced by another particle before that.
+pub(crate) struct Check {
+    tick: u64,
+    /// The number of particles of each [Material], indexed by the id of the material.
+    materials: Vec<usize>,
+    /// The chunks that are simulated during the tick.
+    awake: Vec<Region>,
+}
+
+impl Check {
+    pub fn start(tick: u64, universe: &Universe<Cell>, awake: Vec<Region>) -> Self {
+        Self {
+            tick,
+            materials: count(universe),
+            awake,
+        }
+    }
+
+    /// Checks the invariants after the particles were simulated.
+    pub fn finish(self, universe: &Universe<Cell>, ledger: &Ledger) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        let actual = count(universe);
+        let len = actual
+            .len()
+            .max(self.materials.len())
+            .max(ledger.changes.len());
+        for id in 0..len {
+            let before = self.materials.get(id).copied().unwrap_or(0);
+            let change = ledger.changes.get(id).copied().unwrap_or(0);
+            let expected = before as isize + change;
+            let actual = actual.get(id).copied().unwrap_or(0);
+
+            if expected != actual as isize {
+                violations.push(ViolationKind::MaterialCount {
+                    // ids of materials fit into u16, as they all come from particles or the ledger
+                    material: Material::from_id(id as u16),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        let mut scans = vec![0_u8; universe.area.len()];
+        for pos in &ledger.scanned {
+            let scans = &mut scans[universe.pos_to_i(pos)];
+            *scans = scans.saturating_add(1);
+            if *scans == 2 {
+                violations.push(ViolationKind::ScannedTwice { pos: pos.clone() });
+            }
+        }
+
+        // the positions are not taken from the region, which the steppers use as well
+        for region in &self.awake {
+            for y in region.y.clone() {
+                for x in region.x.clone() {
+                    let pos = Position { x, y };
+                    let i = universe.pos_to_i(&pos);
+                    let cell = &universe.area[i];
+                    if !cell.handled && cell.material != Material::AIR {
+                        let material = cell.material;
+                        violations.push(ViolationKind::Unhandled {
+                            pos: pos.clone(),
+                            material,
+                        });
+                    }
+                    if scans[i] == 0 {
+                        violations.push(ViolationKind::Unscanned { pos });
+                    }
+                }
+            }
+        }
+
+        let handled = universe.area.iter().filter(|cell| cell.handled).count();
+        if ledger.simulated + ledger.displaced != handled {
+            violations.push(ViolationKind::HandledCount {
+                simulated: ledger.simulated,
+                displaced: ledger.displaced,
+                handled,
+            });
+        }
+
+        for (i, cell) in universe.area.iter().enumerate() {
+            let speed = cell.velocity.speed();
+            if !speed.is_finite() || speed > MAX_VELOCITY + VELOCITY_TOLERANCE {
+                violations.push(ViolationKind::Velocity {
+                    pos: universe.i_to_pos(i),
+                    material: cell.material,
+                    velocity: cell.velocity,
+                });
+            }
+        }
+
+        violations
+            .into_iter()
+            .map(|kind| Violation {
+                tick: self.tick,
+                kind,
+            })
+            .collect()
+    }
+}
+
+/// Returns the number of particles of each [Material], indexed by the id of the material.
+fn count(universe: &Universe<Cell>) -> Vec<usize> {
+    let mut counts = Vec::new();
+    for cell in &universe.area {
+        let id = usize::from(cell.material.id());
+        if counts.len() <= id {
+            counts.resize(id + 1, 0);
+        }
+        counts[id] += 1;
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universe() -> Universe<Cell> {
+        let mut universe: Universe<Cell> = Universe::new(2, 1);
+        for cell in &mut universe.area {
+            cell.handled = true;
+        }
+        universe
+    }
+
+    fn check(universe: &Universe<Cell>) -> Check {
+        let region = Region { x: 0..2, y: 0..1 };
+        Check::start(1, universe, vec![region])
+    }
+
+    fn kinds(violations: Vec<Violation>) -> Vec<ViolationKind> {
+        violations
+            .into_iter()
+            .map(|violation| violation.kind)
+            .collect()
+    }
+
+    fn scanned(positions: &[Position]) -> Ledger {
+        let mut ledger = Ledger::default();
+        for pos in positions {
+            ledger.scan(pos);
+            ledger.simulate();
+        }
+        ledger
+    }
+
+    #[test]
+    fn every_cell_is_scanned_once() {
+        let universe = universe();
+        let (a, b) = (Position { x: 0, y: 0 }, Position { x: 1, y: 0 });
+
+        let ledger = scanned(&[a.clone(), b.clone()]);
+        assert_eq!(kinds(check(&universe).finish(&universe, &ledger)), vec![]);
+
+        let mut ledger = scanned(std::slice::from_ref(&b));
+        ledger.displace();
+        assert_eq!(
+            kinds(check(&universe).finish(&universe, &ledger)),
+            vec![ViolationKind::Unscanned { pos: a.clone() }]
+        );
+
+        let mut ledger = scanned(&[a.clone(), a.clone()]);
+        ledger.scanned.push(b);
+        assert_eq!(
+            kinds(check(&universe).finish(&universe, &ledger)),
+            vec![ViolationKind::ScannedTwice { pos: a }]
+        );
+    }
+
+    #[test]
+    fn particles_are_simulated_at_most_once() {
+        let universe = universe();
+        let (a, b) = (Position { x: 0, y: 0 }, Position { x: 1, y: 0 });
+
+        // a particle that is displaced and then simulated is handled twice
+        let mut ledger = scanned(&[a, b]);
+        ledger.displace();
+        assert_eq!(
+            kinds(check(&universe).finish(&universe, &ledger)),
+            vec![ViolationKind::HandledCount {
+                simulated: 2,
+                displaced: 1,
+                handled: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn collisions_explain_material_counts() {
+        let mut universe = universe();
+        let before = check(&universe);
+        universe.area[1].material = Material::SMOKE;
+
+        let mut ledger = scanned(&[Position { x: 0, y: 0 }, Position { x: 1, y: 0 }]);
+        assert_eq!(
+            kinds(before.finish(&universe, &ledger)),
+            vec![
+                ViolationKind::MaterialCount {
+                    material: Material::AIR,
+                    expected: 2,
+                    actual: 1,
+                },
+                ViolationKind::MaterialCount {
+                    material: Material::SMOKE,
+                    expected: 0,
+                    actual: 1,
+                },
+            ]
+        );
+
+        let mut universe = self::universe();
+        let before = check(&universe);
+        universe.area[1].material = Material::SMOKE;
+        ledger.collide((Material::AIR, Material::AIR), &Convert(Material::SMOKE));
+        assert_eq!(kinds(before.finish(&universe, &ledger)), vec![]);
+    }
+}
@@ -0,0 +1,45 @@
+//! The characters that stand for the built-in materials when a universe is drawn as text, e.g. by
+//! the terminal renderer or in golden tests.
+
+use crate::entities::material::Material;
+use crate::sand_sim::Cell;
+
+/// The character of each built-in [Material].
+pub const LEGEND: [(Material, char); 11] = [
+    (Material::SAND, '■'),
+    (Material::SAND_GENERATOR, 'S'),
+    (Material::AIR, ' '),
+    (Material::WATER, '◉'),
+    (Material::WATER_GENERATOR, 'W'),
+    (Material::FIRE, 'f'),
+    (Material::SMOKE, '~'),
+    (Material::VAPOR, '|'),
+    (Material::WOOD, '='),
+    (Material::ICE, '*'),
+    (Material::OIL, 'o'),
+];
+
+/// The character of materials that are not part of the [LEGEND].
+pub const UNKNOWN: char = '?';
+
+/// Returns the character of a [Material], or [UNKNOWN] if it is not a built-in material.
+pub fn to_char(material: Material) -> char {
+    LEGEND
+        .iter()
+        .find(|(m, _)| *m == material)
+        .map_or(UNKNOWN, |(_, c)| *c)
+}
+
+/// Returns the built-in [Material] of a character of the [LEGEND].
+pub fn from_char(c: char) -> Option<Material> {
+    LEGEND.iter().find(|(_, l)| *l == c).map(|(m, _)| *m)
+}
+
+/// Draws an area of cells as one line of characters per row.
+pub fn to_string(area: &[Cell], width: usize) -> String {
+    let lines: Vec<String> = area
+        .chunks(width)
+        .map(|row| row.iter().map(|cell| to_char(cell.material)).collect())
+        .collect();
+    lines.join("\n")
+}
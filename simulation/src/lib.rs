@@ -1,4 +1,17 @@
+mod brush;
+mod chunks;
 pub mod entities;
+pub mod export;
+mod heat;
+pub mod history;
+pub mod import;
+pub mod invariants;
+pub mod legend;
+pub mod observer;
+pub mod replay;
 pub mod sand_sim;
+pub mod snapshot;
+pub mod stats;
 pub mod universe;
 mod utils;
+pub mod world;
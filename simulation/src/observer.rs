@@ -0,0 +1,89 @@
+//! Lets embedders observe the collisions inside a [Simulation], e.g. to play sounds.
+//!
+//! [Simulation]: crate::sand_sim::Simulation
+
+use crate::entities::material::CollisionDesire::{SwapAndMove, SwapAndStop};
+use crate::entities::material::{CollisionDesire, Material};
+use crate::universe::Position;
+
+/// A collision between a particle and its neighbor, which changed at least one of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollisionEvent {
+    /// The position of the particle that collided with its neighbor.
+    pub pos: Position,
+    /// The position of the neighbor.
+    pub neighbor_pos: Position,
+    /// The materials at `pos` and `neighbor_pos` before the collision.
+    pub before: (Material, Material),
+    /// The materials at `pos` and `neighbor_pos` after the collision, which are swapped for moves.
+    pub after: (Material, Material),
+    /// The outcome of the collision.
+    pub outcome: CollisionDesire,
+}
+
+impl CollisionEvent {
+    /// Returns whether the collision moved the particle, instead of reacting with the neighbor.
+    pub fn is_move(&self) -> bool {
+        matches!(self.outcome, SwapAndMove | SwapAndStop)
+    }
+}
+
+/// Receives the [CollisionEvent]s of a simulation, see [Simulation::add_observer].
+///
+/// Events are collected during a tick and delivered after it, in the order the particles were
+/// simulated. [tick] and [par_tick] simulate the particles in different orders, so their events
+/// are delivered in different orders as well. Collisions that are evaded are never reported.
+///
+/// Closures taking a [CollisionEvent] are observers of all reactions.
+///
+/// [Simulation::add_observer]: crate::sand_sim::Simulation::add_observer
+/// [tick]: crate::sand_sim::Simulation::tick
+/// [par_tick]: crate::sand_sim::Simulation::par_tick
+pub trait Observer: Send {
+    fn on_collision(&mut self, event: &CollisionEvent);
+
+    /// Returns whether the observer is interested in moves as well, which are a lot more frequent
+    /// than reactions.
+    fn observes_moves(&self) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(&CollisionEvent) + Send> Observer for F {
+    fn on_collision(&mut self, event: &CollisionEvent) {
+        self(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sand_sim::Simulation;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn closures_observe_reactions_but_not_moves() {
+        let mut sim = Simulation::with_seed(1, 4, 0);
+        let area = [
+            Material::SAND,
+            Material::AIR,
+            Material::FIRE,
+            Material::WATER,
+        ];
+        sim.fill(&area);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = Arc::clone(&events);
+        sim.add_observer(move |event: &CollisionEvent| {
+            observed.lock().unwrap().push(event.clone())
+        });
+        sim.tick();
+
+        let events = events.lock().unwrap();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| !event.is_move()));
+        assert!(events
+            .iter()
+            .all(|event| event.before == (Material::FIRE, Material::WATER)));
+    }
+}
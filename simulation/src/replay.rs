@@ -0,0 +1,700 @@
+//! Records sessions of a [Simulation] and replays them deterministically, e.g. to reproduce bugs.
+//!
+//! A [Replay] consists of a snapshot of the world at the start of the session, the seed of the
+//! simulation and all [Operation]s performed during the session, together with the tick they were
+//! performed at. Replays are stored in a versioned binary format, in little endian:
+//!
+//! - the magic bytes [MAGIC] and the format version as `u16`
+//! - the seed as `u64`, whether [Simulation::par_tick] was used as `u8`, the capacity of the
+//!   [History] as `u64`, whether it records ticks as `u8` and the number of ticks as `u64`
+//! - the material table, like the one of a [snapshot]
+//! - the length of the snapshot as `u64`, followed by the snapshot itself
+//! - the number of events as `u64`, followed by the events. Every event is made of its tick as
+//!   `u64` and the kind of its [Operation] as `u8`, which is followed by its fields in the order
+//!   of their declaration. Positions are stored as two `u32`, sizes as `u32`, materials as their
+//!   index in the material table as `u16`, [Boundaries] like in a [snapshot] and the [Anchor] of
+//!   a resize as `u8`, being 0 for the top left, 1 for the center and 2 for the bottom.
+//!
+//! The kinds of operations are numbered in this order: [Operation::Paint],
+//! [Operation::CommitEdit], [Operation::Undo], [Operation::Redo], [Operation::PaintCircle],
+//! [Operation::PaintRect], [Operation::PaintLine], [Operation::FloodFill],
+//! [Operation::SetBoundaries] and [Operation::Resize].
+
+use crate::entities::material::Material;
+use crate::entities::registry::MaterialRegistry;
+use crate::history::History;
+use crate::sand_sim::{check_size, Simulation};
+use crate::snapshot::{self, read_array, read_u16, read_u32, read_u64, SnapshotError};
+use crate::universe::{Anchor, Boundaries, Position};
+use rand::random;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::{fmt, io};
+
+/// The bytes every replay starts with.
+pub const MAGIC: [u8; 8] = *b"SANDRPLY";
+
+/// The version of the format written by [Replay::write].
+pub const FORMAT_VERSION: u16 = 1;
+
+/// An operation on a [Simulation] that is not part of its ticks, usually caused by user input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    /// See [Simulation::paint].
+    Paint { pos: Position, material: Material },
+    /// See [Simulation::paint_circle].
+    PaintCircle {
+        center: Position,
+        radius: usize,
+        material: Material,
+    },
+    /// See [Simulation::paint_rect].
+    PaintRect {
+        from: Position,
+        to: Position,
+        material: Material,
+    },
+    /// See [Simulation::paint_line].
+    PaintLine {
+        from: Position,
+        to: Position,
+        thickness: usize,
+        material: Material,
+    },
+    /// See [Simulation::flood_fill].
+    FloodFill { pos: Position, material: Material },
+    /// See [Simulation::commit_edit].
+    CommitEdit,
+    /// See [Simulation::undo].
+    Undo,
+    /// See [Simulation::redo].
+    Redo,
+    /// See [Simulation::set_boundaries]. Invalid boundaries are ignored.
+    SetBoundaries(Boundaries),
+    /// See [Simulation::resize].
+    Resize {
+        width: usize,
+        height: usize,
+        anchor: Anchor,
+    },
+}
+
+impl Operation {
+    /// Performs the operation on a [Simulation].
+    pub fn apply(&self, sim: &mut Simulation) {
+        match self {
+            // invalid paint operations are ignored during the recording, too
+            Operation::Paint { pos, material } => {
+                let _ = sim.paint(pos, *material);
+            }
+            Operation::PaintCircle {
+                center,
+                radius,
+                material,
+            } => {
+                let _ = sim.paint_circle(center, *radius, *material);
+            }
+            Operation::PaintRect { from, to, material } => {
+                let _ = sim.paint_rect(from, to, *material);
+            }
+            Operation::PaintLine {
+                from,
+                to,
+                thickness,
+                material,
+            } => {
+                let _ = sim.paint_line(from, to, *thickness, *material);
+            }
+            Operation::FloodFill { pos, material } => {
+                let _ = sim.flood_fill(pos, *material);
+            }
+            Operation::SetBoundaries(boundaries) => {
+                // invalid boundaries are ignored during the recording, too
+                let _ = sim.set_boundaries(*boundaries);
+            }
+            Operation::Resize {
+                width,
+                height,
+                anchor,
+            } => {
+                // invalid sizes are ignored during the recording, too
+                let _ = sim.resize(*width, *height, *anchor);
+            }
+            Operation::CommitEdit => sim.commit_edit(),
+            Operation::Undo => {
+                sim.undo();
+            }
+            Operation::Redo => {
+                sim.redo();
+            }
+        }
+    }
+}
+
+/// An [Operation] that was performed after the given number of ticks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub tick: u64,
+    pub operation: Operation,
+}
+
+/// A recorded session of a [Simulation], see [Recorder].
+#[derive(Clone)]
+pub struct Replay {
+    materials: MaterialRegistry,
+    seed: u64,
+    parallel: bool,
+    history_capacity: usize,
+    record_ticks: bool,
+    snapshot: Vec<u8>,
+    ticks: u64,
+    events: Vec<Event>,
+}
+
+impl Replay {
+    /// Returns the number of ticks of the session.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Writes the replay in the format described in the [module docs](self).
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), ReplayError> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&[u8::from(self.parallel)])?;
+        writer.write_all(&(self.history_capacity as u64).to_le_bytes())?;
+        writer.write_all(&[u8::from(self.record_ticks)])?;
+        writer.write_all(&self.ticks.to_le_bytes())?;
+        snapshot::write_material_table(writer, &self.materials)?;
+
+        writer.write_all(&(self.snapshot.len() as u64).to_le_bytes())?;
+        writer.write_all(&self.snapshot)?;
+
+        writer.write_all(&(self.events.len() as u64).to_le_bytes())?;
+        for Event { tick, operation } in &self.events {
+            writer.write_all(&tick.to_le_bytes())?;
+            match operation {
+                Operation::Paint { pos, material } => {
+                    writer.write_all(&[0])?;
+                    write_pos(writer, pos)?;
+                    writer.write_all(&material.id().to_le_bytes())?;
+                }
+                Operation::CommitEdit => writer.write_all(&[1])?,
+                Operation::Undo => writer.write_all(&[2])?,
+                Operation::Redo => writer.write_all(&[3])?,
+                Operation::PaintCircle {
+                    center,
+                    radius,
+                    material,
+                } => {
+                    writer.write_all(&[4])?;
+                    write_pos(writer, center)?;
+                    write_size(writer, *radius)?;
+                    writer.write_all(&material.id().to_le_bytes())?;
+                }
+                Operation::PaintRect { from, to, material } => {
+                    writer.write_all(&[5])?;
+                    write_pos(writer, from)?;
+                    write_pos(writer, to)?;
+                    writer.write_all(&material.id().to_le_bytes())?;
+                }
+                Operation::PaintLine {
+                    from,
+                    to,
+                    thickness,
+                    material,
+                } => {
+                    writer.write_all(&[6])?;
+                    write_pos(writer, from)?;
+                    write_pos(writer, to)?;
+                    write_size(writer, *thickness)?;
+                    writer.write_all(&material.id().to_le_bytes())?;
+                }
+                Operation::FloodFill { pos, material } => {
+                    writer.write_all(&[7])?;
+                    write_pos(writer, pos)?;
+                    writer.write_all(&material.id().to_le_bytes())?;
+                }
+                Operation::SetBoundaries(boundaries) => {
+                    writer.write_all(&[8])?;
+                    snapshot::write_boundaries(writer, boundaries)?;
+                }
+                Operation::Resize {
+                    width,
+                    height,
+                    anchor,
+                } => {
+                    writer.write_all(&[9])?;
+                    write_size(writer, *width)?;
+                    write_size(writer, *height)?;
+                    let anchor = match anchor {
+                        Anchor::TopLeft => 0,
+                        Anchor::Center => 1,
+                        Anchor::Bottom => 2,
+                    };
+                    writer.write_all(&[anchor])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a replay written by [Replay::write], with its materials being mapped to the ones of
+    /// the given [MaterialRegistry] by name.
+    pub fn read(reader: &mut impl Read, materials: MaterialRegistry) -> Result<Self, ReplayError> {
+        if read_array::<8>(reader)? != MAGIC {
+            return Err(ReplayError::InvalidMagic);
+        }
+
+        let version = read_u16(reader)?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+
+        let seed = read_u64(reader)?;
+        let parallel = read_flag(reader)?;
+        let history_capacity =
+            usize::try_from(read_u64(reader)?).map_err(|_| ReplayError::InvalidHeader)?;
+        let record_ticks = read_flag(reader)?;
+        let ticks = read_u64(reader)?;
+        let table = snapshot::read_material_table(reader, &materials)?;
+
+        let len = read_u64(reader)?;
+        let mut snapshot = Vec::new();
+        reader.take(len).read_to_end(&mut snapshot)?;
+        if snapshot.len() as u64 != len {
+            return Err(ReplayError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        let count = read_u64(reader)?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let tick = read_u64(reader)?;
+            if tick > ticks || events.last().is_some_and(|last: &Event| last.tick > tick) {
+                return Err(ReplayError::InvalidEvent);
+            }
+
+            let operation = match read_array::<1>(reader)? {
+                [0] => Operation::Paint {
+                    pos: read_pos(reader)?,
+                    material: read_material(reader, &table)?,
+                },
+                [1] => Operation::CommitEdit,
+                [2] => Operation::Undo,
+                [3] => Operation::Redo,
+                [4] => Operation::PaintCircle {
+                    center: read_pos(reader)?,
+                    radius: read_u32(reader)? as usize,
+                    material: read_material(reader, &table)?,
+                },
+                [5] => Operation::PaintRect {
+                    from: read_pos(reader)?,
+                    to: read_pos(reader)?,
+                    material: read_material(reader, &table)?,
+                },
+                [6] => Operation::PaintLine {
+                    from: read_pos(reader)?,
+                    to: read_pos(reader)?,
+                    thickness: read_u32(reader)? as usize,
+                    material: read_material(reader, &table)?,
+                },
+                [7] => Operation::FloodFill {
+                    pos: read_pos(reader)?,
+                    material: read_material(reader, &table)?,
+                },
+                [8] => {
+                    let boundaries = snapshot::read_boundaries(reader).map_err(|e| match e {
+                        SnapshotError::Io(e) => ReplayError::Io(e),
+                        _ => ReplayError::InvalidEvent,
+                    })?;
+                    Operation::SetBoundaries(boundaries)
+                }
+                [9] => {
+                    let width = read_u32(reader)? as usize;
+                    let height = read_u32(reader)? as usize;
+                    check_size(width, height).map_err(|_| ReplayError::InvalidEvent)?;
+
+                    Operation::Resize {
+                        width,
+                        height,
+                        anchor: match read_array::<1>(reader)? {
+                            [0] => Anchor::TopLeft,
+                            [1] => Anchor::Center,
+                            [2] => Anchor::Bottom,
+                            _ => return Err(ReplayError::InvalidEvent),
+                        },
+                    }
+                }
+                _ => return Err(ReplayError::InvalidEvent),
+            };
+            events.push(Event { tick, operation });
+        }
+
+        Ok(Self {
+            materials,
+            seed,
+            parallel,
+            history_capacity,
+            record_ticks,
+            snapshot,
+            ticks,
+            events,
+        })
+    }
+}
+
+fn write_pos(writer: &mut impl Write, pos: &Position) -> io::Result<()> {
+    write_size(writer, pos.x)?;
+    write_size(writer, pos.y)
+}
+
+fn write_size(writer: &mut impl Write, size: usize) -> io::Result<()> {
+    // the dimensions of a snapshot fit into u32, so larger positions are outside of the universe
+    // either way, and larger sizes cover all of it
+    let size = u32::try_from(size).unwrap_or(u32::MAX);
+    writer.write_all(&size.to_le_bytes())
+}
+
+fn read_pos(reader: &mut impl Read) -> io::Result<Position> {
+    Ok(Position::new(
+        read_u32(reader)? as usize,
+        read_u32(reader)? as usize,
+    ))
+}
+
+fn read_material(reader: &mut impl Read, table: &[Material]) -> Result<Material, ReplayError> {
+    let index = read_u16(reader)?;
+
+    table
+        .get(usize::from(index))
+        .copied()
+        .ok_or(ReplayError::InvalidEvent)
+}
+
+fn read_flag(reader: &mut impl Read) -> Result<bool, ReplayError> {
+    match read_array::<1>(reader)? {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(ReplayError::InvalidHeader),
+    }
+}
+
+/// Records a session of a [Simulation] into a [Replay].
+///
+/// All operations and ticks during the session have to go through the recorder, so that they can
+/// be replayed in the same order.
+pub struct Recorder {
+    replay: Replay,
+}
+
+impl Recorder {
+    /// Starts recording a session of a [Simulation], which is ticked with [Simulation::par_tick]
+    /// if `parallel` is set, or [Simulation::tick] otherwise.
+    ///
+    /// The simulation is reseeded, and its [History] is cleared, so that its state is fully
+    /// captured by the replay.
+    pub fn start(sim: &mut Simulation, parallel: bool) -> Result<Self, SnapshotError> {
+        let seed = random();
+        sim.reseed(seed);
+
+        let history = sim.history();
+        let (history_capacity, record_ticks) = (history.capacity(), history.records_ticks());
+        sim.set_history(History::new(history_capacity, record_ticks));
+
+        let mut snapshot = Vec::new();
+        sim.save(&mut snapshot)?;
+
+        Ok(Self {
+            replay: Replay {
+                materials: sim.materials().clone(),
+                seed,
+                parallel,
+                history_capacity,
+                record_ticks,
+                snapshot,
+                ticks: 0,
+                events: Vec::new(),
+            },
+        })
+    }
+
+    /// Performs an [Operation] on the [Simulation] and records it. Resizing to a size that
+    /// [Simulation::resize] rejects is not recorded.
+    pub fn apply(&mut self, sim: &mut Simulation, operation: Operation) {
+        operation.apply(sim);
+        // such a resize does nothing, and would make the replay unreadable
+        if let Operation::Resize { width, height, .. } = operation {
+            if check_size(width, height).is_err() {
+                return;
+            }
+        }
+        self.replay.events.push(Event {
+            tick: self.replay.ticks,
+            operation,
+        });
+    }
+
+    /// Advances the [Simulation] by one step and records it.
+    pub fn tick(&mut self, sim: &mut Simulation) {
+        if self.replay.parallel {
+            sim.par_tick();
+        } else {
+            sim.tick();
+        }
+        self.replay.ticks += 1;
+    }
+
+    /// Stops recording and returns the [Replay] of the session.
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+/// Re-runs the session of a [Replay] tick by tick.
+pub struct Player {
+    sim: Simulation,
+    parallel: bool,
+    ticks: u64,
+    tick: u64,
+    events: Vec<Event>,
+    next_event: usize,
+}
+
+impl Player {
+    pub fn new(replay: &Replay) -> Result<Self, SnapshotError> {
+        let mut sim = Simulation::load_with_materials(
+            &mut replay.snapshot.as_slice(),
+            replay.seed,
+            replay.materials.clone(),
+        )?;
+        sim.set_history(History::new(replay.history_capacity, replay.record_ticks));
+
+        Ok(Self {
+            sim,
+            parallel: replay.parallel,
+            ticks: replay.ticks,
+            tick: 0,
+            events: replay.events.clone(),
+            next_event: 0,
+        })
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.sim
+    }
+
+    /// Returns the number of ticks replayed so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tick == self.ticks && self.next_event == self.events.len()
+    }
+
+    /// Performs the operations of the current tick and advances the simulation by one step.
+    /// Returns whether there was a step left to replay.
+    pub fn step(&mut self) -> bool {
+        while let Some(event) = self.events.get(self.next_event) {
+            if event.tick > self.tick {
+                break;
+            }
+            event.operation.apply(&mut self.sim);
+            self.next_event += 1;
+        }
+
+        if self.tick == self.ticks {
+            return false;
+        }
+
+        if self.parallel {
+            self.sim.par_tick();
+        } else {
+            self.sim.tick();
+        }
+        self.tick += 1;
+
+        true
+    }
+
+    /// Replays the rest of the session and returns the resulting [Simulation].
+    pub fn finish(mut self) -> Simulation {
+        while self.step() {}
+
+        self.sim
+    }
+}
+
+/// An error that occurred while reading or writing a replay.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// The data does not start with [MAGIC], so it is not a replay.
+    InvalidMagic,
+    /// The replay was written by a newer, or an invalid, version of the format.
+    UnsupportedVersion(u16),
+    /// The header of the replay contains invalid values.
+    InvalidHeader,
+    /// The snapshot or the material table of the replay is invalid.
+    Snapshot(SnapshotError),
+    /// An event has an unknown operation, an invalid material or size, or is out of order.
+    InvalidEvent,
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read or write replay: {e}"),
+            Self::InvalidMagic => write!(f, "not a replay"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported replay version {version}, expected at most {FORMAT_VERSION}"
+            ),
+            Self::InvalidHeader => write!(f, "replay has an invalid header"),
+            Self::Snapshot(e) => write!(f, "replay has an invalid snapshot: {e}"),
+            Self::InvalidEvent => write!(f, "replay contains an invalid event"),
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Snapshot(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<SnapshotError> for ReplayError {
+    fn from(e: SnapshotError) -> Self {
+        Self::Snapshot(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::universe::Boundary;
+
+    /// Records a short session that uses every kind of [Operation].
+    fn record(parallel: bool) -> (Replay, Simulation) {
+        let mut sim = Simulation::with_seed(40, 30, 0);
+        let mut recorder = Recorder::start(&mut sim, parallel).unwrap();
+
+        let operations = [
+            Operation::Paint {
+                pos: Position { x: 3, y: 4 },
+                material: Material::SAND,
+            },
+            Operation::PaintCircle {
+                center: Position { x: 20, y: 5 },
+                radius: 3,
+                material: Material::WATER,
+            },
+            Operation::PaintRect {
+                from: Position { x: 0, y: 25 },
+                to: Position { x: 39, y: 29 },
+                material: Material::WOOD,
+            },
+            Operation::CommitEdit,
+            Operation::PaintLine {
+                from: Position { x: 5, y: 10 },
+                to: Position { x: 30, y: 15 },
+                thickness: 2,
+                material: Material::SAND,
+            },
+            Operation::Undo,
+            Operation::Redo,
+            Operation::FloodFill {
+                pos: Position { x: 1, y: 1 },
+                material: Material::AIR,
+            },
+            Operation::SetBoundaries(Boundaries {
+                left: Boundary::Wrap,
+                right: Boundary::Wrap,
+                top: Boundary::Wall,
+                bottom: Boundary::Void,
+            }),
+            Operation::Resize {
+                width: 50,
+                height: 20,
+                anchor: Anchor::Bottom,
+            },
+        ];
+        for operation in operations {
+            recorder.apply(&mut sim, operation);
+            recorder.tick(&mut sim);
+            recorder.tick(&mut sim);
+        }
+
+        (recorder.finish(), sim)
+    }
+
+    fn write(replay: &Replay) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        replay.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn written_replays_can_be_read() {
+        let (replay, _) = record(false);
+        let bytes = write(&replay);
+
+        let read = Replay::read(&mut bytes.as_slice(), MaterialRegistry::builtin()).unwrap();
+        assert_eq!(read.ticks(), replay.ticks());
+        assert_eq!(read.events(), replay.events());
+        assert_eq!(write(&read), bytes);
+    }
+
+    #[test]
+    fn replays_reproduce_the_session() {
+        for parallel in [false, true] {
+            let (replay, sim) = record(parallel);
+            let replayed = Player::new(&replay).unwrap().finish();
+
+            assert_eq!(replayed.universe().area, sim.universe().area);
+            assert_eq!(
+                replayed.universe().boundaries(),
+                sim.universe().boundaries()
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        let (replay, _) = record(false);
+        let mut bytes = write(&replay);
+        // the last event resizes the universe, and ends with its width, height and anchor
+        let len = bytes.len();
+        bytes[len - 9..len - 1].copy_from_slice(&[0xff; 8]);
+
+        let result = Replay::read(&mut bytes.as_slice(), MaterialRegistry::builtin());
+        assert!(matches!(result, Err(ReplayError::InvalidEvent)));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let (replay, _) = record(false);
+        let mut bytes = write(&replay);
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        let result = Replay::read(&mut bytes.as_slice(), MaterialRegistry::builtin());
+        assert!(matches!(result, Err(ReplayError::UnsupportedVersion(_))));
+    }
+}
@@ -1,27 +1,470 @@
-use crate::entities::cell_content::Particle;
-use crate::entities::direction::ExtDirIterator;
+use crate::brush;
+use crate::chunks::{Chunks, Region, SharedArea, MAX_VELOCITY};
+use crate::entities::cell_content::{Particle, Velocity};
+use crate::entities::direction::{Direction, ExtDirIterator};
 use crate::entities::material::CollisionDesire::{
     Consume, Convert, Eradicate, Evade, GetConverted, SwapAndMove, SwapAndStop,
 };
-use crate::entities::material::Material;
-use crate::universe::{Position, Universe};
+use crate::entities::material::{CollisionDesire, Material, MaterialBehavior};
+use crate::entities::registry::{DefinitionError, MaterialRegistry};
+use crate::heat;
+use crate::history::History;
+use crate::invariants::{Check, Ledger, Violation};
+use crate::observer::{CollisionEvent, Observer};
+use crate::snapshot::{self, SnapshotError};
+use crate::stats::TickStats;
+use crate::universe::{Anchor, Boundaries, BoundaryError, Neighbor, Position, Universe};
+use crate::utils::SimRng;
 
+use rand::{random, Rng, SeedableRng};
 use rayon::current_num_threads;
 use rayon::prelude::*;
-use std::sync::{Mutex, MutexGuard};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::ops::AddAssign;
+use std::{fmt, mem};
 
-pub type Cell = Mutex<Particle>;
+pub type Cell = Particle;
+
+/// The largest number of cells a [Universe] can be resized to, see [Simulation::resize].
+pub const MAX_CELLS: usize = 1 << 24;
 
 /// Simulates the behaviour of [Material] in a [Universe] per tick
+///
+/// Chunks of the [Universe] that are at rest are skipped until they are changed again, either by
+/// their neighbors or through [Simulation::get_cell_mut] and the fill methods.
 pub struct Simulation {
-    pub universe: Universe<Cell>,
+    universe: Universe<Cell>,
+    materials: MaterialRegistry,
+    rng: SimRng,
+    chunks: Chunks,
+    history: History,
+    collect_stats: bool,
+    stats: Option<TickStats>,
+    observers: Vec<Box<dyn Observer>>,
+    /// The number of particles that have crossed a [Boundary::Void] so far.
+    ///
+    /// [Boundary::Void]: crate::universe::Boundary::Void
+    voided: usize,
+    /// The number of ticks simulated so far.
+    ticks: u64,
+    check_invariants: bool,
+    violations: Vec<Violation>,
 }
 
 impl Simulation {
+    /// Initializes a new Simulation with a random seed.
     pub fn new(width: usize, height: usize) -> Self {
+        Self::with_seed(width, height, random())
+    }
+
+    /// Initializes a new Simulation whose randomness is derived from the given seed.
+    ///
+    /// Given the same seed and starting world, [Simulation::tick] always produces the same results.
+    pub fn with_seed(width: usize, height: usize, seed: u64) -> Self {
+        Self::with_materials(width, height, seed, MaterialRegistry::builtin())
+    }
+
+    /// Initializes a new Simulation of the materials in the given [MaterialRegistry], whose
+    /// randomness is derived from the given seed.
+    pub fn with_materials(
+        width: usize,
+        height: usize,
+        seed: u64,
+        materials: MaterialRegistry,
+    ) -> Self {
         Self {
             universe: Universe::new(width, height),
+            materials,
+            rng: SimRng::seed_from_u64(seed),
+            chunks: Chunks::new(width, height, &Boundaries::default()),
+            history: History::default(),
+            collect_stats: false,
+            stats: None,
+            observers: Vec::new(),
+            voided: 0,
+            ticks: 0,
+            check_invariants: false,
+            violations: Vec::new(),
+        }
+    }
+
+    /// Loads a simulation of the built-in materials from a snapshot written by [Simulation::save].
+    ///
+    /// The state of the random number generator is not part of a snapshot, so the loaded
+    /// simulation gets a random seed.
+    pub fn load(reader: &mut impl Read) -> Result<Self, SnapshotError> {
+        Self::load_with_materials(reader, random(), MaterialRegistry::builtin())
+    }
+
+    /// Loads a simulation of the materials in the given [MaterialRegistry] from a snapshot written
+    /// by [Simulation::save], whose randomness is derived from the given seed.
+    ///
+    /// The materials of the snapshot are looked up by name, so they all have to be registered.
+    pub fn load_with_materials(
+        reader: &mut impl Read,
+        seed: u64,
+        materials: MaterialRegistry,
+    ) -> Result<Self, SnapshotError> {
+        let universe = snapshot::read(reader, &materials)?;
+        let chunks = Chunks::new(universe.width, universe.height, &universe.boundaries);
+
+        Ok(Self {
+            universe,
+            materials,
+            rng: SimRng::seed_from_u64(seed),
+            chunks,
+            history: History::default(),
+            collect_stats: false,
+            stats: None,
+            observers: Vec::new(),
+            voided: 0,
+            ticks: 0,
+            check_invariants: false,
+            violations: Vec::new(),
+        })
+    }
+
+    /// Writes a snapshot of the [Universe] of the simulation, including the full state of all of
+    /// its particles. See [snapshot] for the format.
+    pub fn save(&self, writer: &mut impl Write) -> Result<(), SnapshotError> {
+        snapshot::write(writer, &self.universe, &self.materials)
+    }
+
+    /// Restarts the randomness of the simulation with the given seed and wakes up all chunks.
+    ///
+    /// Afterwards, the simulation continues exactly like one loaded from a snapshot of its current
+    /// state with [Simulation::load_with_materials] and the same seed.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = SimRng::seed_from_u64(seed);
+        let universe = &self.universe;
+        self.chunks = Chunks::new(universe.width, universe.height, &universe.boundaries);
+    }
+
+    /// Returns the [Universe] of the simulation.
+    pub fn universe(&self) -> &Universe<Cell> {
+        &self.universe
+    }
+
+    /// Sets what happens at the edges of the [Universe], which are all walls by default, and wakes
+    /// up all chunks.
+    pub fn set_boundaries(&mut self, boundaries: Boundaries) -> Result<(), BoundaryError> {
+        boundaries.validate()?;
+        self.universe.boundaries = boundaries;
+        let universe = &self.universe;
+        self.chunks = Chunks::new(universe.width, universe.height, &universe.boundaries);
+
+        Ok(())
+    }
+
+    /// Changes the size of the [Universe]. Particles keep their place relative to the [Anchor],
+    /// particles that end up outside of the universe are removed, and new space is filled with
+    /// air.
+    ///
+    /// The [History] is cleared, as its changes refer to cells of the old size, and all chunks are
+    /// woken up.
+    ///
+    /// Fails with a [SimError::InvalidSize] if the new universe would have more than [MAX_CELLS]
+    /// cells, in which case nothing changes.
+    pub fn resize(&mut self, width: usize, height: usize, anchor: Anchor) -> Result<(), SimError> {
+        check_size(width, height)?;
+
+        let old = &self.universe;
+        let (dx, dy) = anchor.offset((old.width, old.height), (width, height));
+
+        let mut universe = Universe {
+            area: vec![self.materials.particle(Material::AIR); width * height],
+            width,
+            height,
+            boundaries: old.boundaries,
+        };
+        for (i, cell) in old.area.iter().enumerate() {
+            let pos = old.i_to_pos(i);
+            let x = pos.x as isize + dx;
+            let y = pos.y as isize + dy;
+            if (0..width as isize).contains(&x) && (0..height as isize).contains(&y) {
+                universe.area[y as usize * width + x as usize] = cell.clone();
+            }
+        }
+
+        self.chunks = Chunks::new(width, height, &universe.boundaries);
+        self.universe = universe;
+        self.history = History::new(self.history.capacity(), self.history.records_ticks());
+
+        Ok(())
+    }
+
+    /// Returns the number of particles that have been deleted by crossing a [Boundary::Void] since
+    /// the simulation was created or loaded.
+    ///
+    /// [Boundary::Void]: crate::universe::Boundary::Void
+    pub fn voided(&self) -> usize {
+        self.voided
+    }
+
+    /// Returns the [MaterialRegistry] of the simulation.
+    pub fn materials(&self) -> &MaterialRegistry {
+        &self.materials
+    }
+
+    /// Registers the behaviour of a material with the simulation and returns its id.
+    ///
+    /// See [MaterialRegistry::register].
+    pub fn register_material(
+        &mut self,
+        behavior: impl MaterialBehavior + 'static,
+    ) -> Result<Material, DefinitionError> {
+        let material = self.materials.register(behavior)?;
+        // existing particles of the material might behave differently now
+        self.chunks.mark_all_changed();
+
+        Ok(material)
+    }
+
+    /// Adds an [Observer], which is notified about the collisions of every tick.
+    pub fn add_observer(&mut self, observer: impl Observer + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Removes all observers of the simulation.
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Enables or disables collecting [TickStats] during every tick, which is disabled by default.
+    pub fn set_collect_stats(&mut self, collect: bool) {
+        self.collect_stats = collect;
+        if !collect {
+            self.stats = None;
+        }
+    }
+
+    /// Returns the [TickStats] of the last tick, or [None] if they are not collected.
+    pub fn stats(&self) -> Option<&TickStats> {
+        self.stats.as_ref()
+    }
+
+    /// Returns the number of ticks the simulation has been advanced by since it was created.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Enables or disables checking the [invariants] after every tick, which is disabled by
+    /// default as it slows down ticks considerably.
+    ///
+    /// Violations are collected until they are taken with [Simulation::take_violations].
+    ///
+    /// [invariants]: crate::invariants
+    pub fn set_check_invariants(&mut self, check: bool) {
+        self.check_invariants = check;
+    }
+
+    /// Returns the [Violation]s of invariants found since the last call, see
+    /// [Simulation::set_check_invariants].
+    pub fn take_violations(&mut self) -> Vec<Violation> {
+        mem::take(&mut self.violations)
+    }
+
+    /// Returns the [History] of the simulation.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Replaces the [History] of the simulation, which is disabled by default.
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    /// Turns all edits since the last commit into a single change of the [History], which is
+    /// undone and redone at once.
+    pub fn commit_edit(&mut self) {
+        self.history.commit(&self.universe.area);
+    }
+
+    /// Reverts the latest change of the [History], committing pending edits first. Returns
+    /// whether there was a change to undo.
+    pub fn undo(&mut self) -> bool {
+        let (universe, chunks) = (&mut self.universe, &self.chunks);
+        let width = universe.width;
+
+        self.history.undo(&mut universe.area, |i| {
+            chunks.mark_changed(&Position::new(i % width, i / width))
+        })
+    }
+
+    /// Restores the latest change of the [History] that has been undone. Returns whether there was
+    /// a change to redo.
+    pub fn redo(&mut self) -> bool {
+        let (universe, chunks) = (&mut self.universe, &self.chunks);
+        let width = universe.width;
+
+        self.history.redo(&mut universe.area, |i| {
+            chunks.mark_changed(&Position::new(i % width, i / width))
+        })
+    }
+
+    /// Returns the cell at the given position, or a [SimError::OutOfBounds] if the position is
+    /// outside of the universe.
+    pub fn try_get_cell(&self, pos: &Position) -> Result<&Cell, SimError> {
+        self.check_bounds(pos)?;
+
+        Ok(&self.universe.area[self.universe.pos_to_i(pos)])
+    }
+
+    /// Returns a mutable cell at the given position, or [None] if position is outside of area.
+    ///
+    /// The cell is marked as changed, so that a resting chunk around it is woken up, and its
+    /// current content is recorded in the [History].
+    pub fn get_cell_mut(&mut self, pos: &Position) -> Option<&mut Cell> {
+        self.try_get_cell_mut(pos).ok()
+    }
+
+    /// Like [Simulation::get_cell_mut], but returns a [SimError::OutOfBounds] if the position is
+    /// outside of the universe.
+    pub fn try_get_cell_mut(&mut self, pos: &Position) -> Result<&mut Cell, SimError> {
+        self.check_bounds(pos)?;
+
+        let index = self.universe.pos_to_i(pos);
+        let cell = &mut self.universe.area[index];
+        self.history.record_edit(index, cell);
+        self.chunks.mark_changed(pos);
+
+        Ok(cell)
+    }
+
+    /// Returns the [Material] with the given name, ignoring case, see [MaterialRegistry::find].
+    pub fn find_material(&self, name: &str) -> Result<Material, SimError> {
+        self.materials
+            .find(name)
+            .ok_or_else(|| SimError::UnknownMaterialName(name.to_string()))
+    }
+
+    /// Replaces the cell at the given position with a new particle of a [Material].
+    ///
+    /// Like all paint methods, this goes through [Simulation::get_cell_mut], so the change wakes
+    /// up resting chunks and is recorded in the [History]. All paint methods fail if the material
+    /// is not registered with the simulation.
+    pub fn paint(&mut self, pos: &Position, material: Material) -> Result<(), SimError> {
+        self.check_bounds(pos)?;
+        self.paint_cells([pos.clone()], material)
+    }
+
+    /// Fills a circle around `center` with new particles of a [Material], clipped to the universe.
+    /// A radius of zero paints a single cell.
+    pub fn paint_circle(
+        &mut self,
+        center: &Position,
+        radius: usize,
+        material: Material,
+    ) -> Result<(), SimError> {
+        let (width, height) = (self.universe.width, self.universe.height);
+        let cells: Vec<_> = brush::circle(center, radius, width, height).collect();
+        self.paint_cells(cells, material)
+    }
+
+    /// Fills the rectangle spanned by two opposite corners with new particles of a [Material],
+    /// clipped to the universe. Both corners are part of the rectangle.
+    pub fn paint_rect(
+        &mut self,
+        from: &Position,
+        to: &Position,
+        material: Material,
+    ) -> Result<(), SimError> {
+        let (width, height) = (self.universe.width, self.universe.height);
+        self.paint_cells(brush::rect(from, to, width, height), material)
+    }
+
+    /// Draws a line with round caps from `from` to `to` with new particles of a [Material],
+    /// clipped to the universe. The line is `thickness` cells wide, but at least one.
+    pub fn paint_line(
+        &mut self,
+        from: &Position,
+        to: &Position,
+        thickness: usize,
+        material: Material,
+    ) -> Result<(), SimError> {
+        let (width, height) = (self.universe.width, self.universe.height);
+        let cells: Vec<_> = brush::line(from, to, thickness, width, height).collect();
+        self.paint_cells(cells, material)
+    }
+
+    /// Replaces the particles of the area around the given position that is made of the same
+    /// [Material] with new particles of another material. Cells belong to the area if they are
+    /// connected through their left, right, upper or lower neighbors.
+    ///
+    /// Nothing happens if the position is already made of `material`.
+    pub fn flood_fill(&mut self, pos: &Position, material: Material) -> Result<(), SimError> {
+        let target = self.try_get_cell(pos)?.material;
+        self.check_material(material)?;
+        if target == material {
+            return Ok(());
         }
+
+        let particle = self.materials.particle(material);
+        let mut stack = vec![pos.clone()];
+        while let Some(pos) = stack.pop() {
+            // cells that are already filled are made of another material
+            if self.universe.get_cell(&pos).map(|cell| cell.material) != Some(target) {
+                continue;
+            }
+            if let Some(cell) = self.get_cell_mut(&pos) {
+                *cell = particle.clone();
+            }
+
+            for dir in [
+                Direction::Left,
+                Direction::Right,
+                Direction::Up,
+                Direction::Down,
+            ] {
+                if let Some(neighbor) = self.universe.get_neighbor_pos(&pos, &dir) {
+                    stack.push(neighbor);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the cells at the given positions, which have to be inside of the universe.
+    fn paint_cells(
+        &mut self,
+        cells: impl IntoIterator<Item = Position>,
+        material: Material,
+    ) -> Result<(), SimError> {
+        self.check_material(material)?;
+
+        let particle = self.materials.particle(material);
+        for pos in cells {
+            if let Some(cell) = self.get_cell_mut(&pos) {
+                *cell = particle.clone();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_bounds(&self, pos: &Position) -> Result<(), SimError> {
+        if !self.universe.contains(pos) {
+            return Err(SimError::OutOfBounds {
+                pos: pos.clone(),
+                width: self.universe.width,
+                height: self.universe.height,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns a [SimError::UnknownMaterial] if the [Material] is not registered with the
+    /// simulation.
+    pub(crate) fn check_material(&self, material: Material) -> Result<(), SimError> {
+        if usize::from(material.id()) >= self.materials.len() {
+            return Err(SimError::UnknownMaterial(material));
+        }
+
+        Ok(())
     }
 
     /// Returns the number of threads being used
@@ -33,184 +476,1212 @@ impl Simulation {
     }
 
     /// Advances the simulation by one step.
-    pub fn tick(&self) {
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        let before = self.start_recording_tick();
         self.set_all_unhandled();
-        self.simulate();
+        let check = self.start_check();
+        let report = self.simulate();
+        self.finish_check(check, &report);
+        self.conduct_heat();
+        self.chunks.settle();
+        self.finish_recording_tick(before);
+        self.voided += report.stats.voided;
+        self.finish_stats(report.stats, false);
+        self.notify(&report.events);
     }
 
     /// Advances the simulation by one step. Uses multithreading where possible.
-    pub fn par_tick(&self) -> usize {
+    pub fn par_tick(&mut self) {
+        self.ticks += 1;
+        let before = self.start_recording_tick();
         self.par_set_all_unhandled();
-        self.par_simulate()
+        let check = self.start_check();
+        let report = self.par_simulate();
+        self.finish_check(check, &report);
+        self.par_conduct_heat();
+        self.chunks.settle();
+        self.finish_recording_tick(before);
+        self.voided += report.stats.voided;
+        self.finish_stats(report.stats, true);
+        self.notify(&report.events);
     }
 
-    fn simulate(&self) {
-        let failed_locks = Mutex::new(0usize);
+    /// Advances a part of a larger world by one step, see [World].
+    ///
+    /// Only the chunks that `awake` returns true for are simulated, the others merely provide room
+    /// for the particles that leave them. Returns the universe and its chunks, which tell which
+    /// parts of it changed.
+    ///
+    /// A world advances several parts per step, so unlike [Simulation::tick], particles are not
+    /// marked as unhandled beforehand, and neither the number of ticks, the [History], the stats
+    /// nor the observers of the simulation are updated.
+    ///
+    /// [World]: crate::world::World
+    pub(crate) fn tick_area(
+        &mut self,
+        universe: Universe<Cell>,
+        awake: impl Fn(&Region) -> bool,
+        parallel: bool,
+    ) -> (Universe<Cell>, Chunks) {
+        self.chunks = Chunks::new(universe.width, universe.height, &universe.boundaries);
+        self.chunks.freeze_unless(awake);
+        self.universe = universe;
 
-        for index in (0..self.universe.area.len()).rev() {
-            let pos = self.universe.i_to_pos(index);
-            self.handle_collision(&pos, &failed_locks);
+        if parallel {
+            self.par_simulate();
+            self.par_conduct_heat();
+        } else {
+            self.simulate();
+            self.conduct_heat();
+        }
+        self.chunks.settle();
+
+        let empty = Universe::new(0, 0);
+        let chunks = Chunks::new(0, 0, &empty.boundaries);
+        (
+            mem::replace(&mut self.universe, empty),
+            mem::replace(&mut self.chunks, chunks),
+        )
+    }
+
+    /// Returns which [CollisionEvent]s the observers of the simulation are interested in.
+    fn observe(&self) -> Observe {
+        if self
+            .observers
+            .iter()
+            .any(|observer| observer.observes_moves())
+        {
+            Observe::Everything
+        } else if !self.observers.is_empty() {
+            Observe::Reactions
+        } else {
+            Observe::Nothing
         }
     }
 
-    fn par_simulate(&self) -> usize {
-        let failed_locks = Mutex::new(0usize);
+    /// Delivers the [CollisionEvent]s of a tick to the observers of the simulation.
+    fn notify(&mut self, events: &[CollisionEvent]) {
+        for observer in &mut self.observers {
+            let moves = observer.observes_moves();
+            for event in events.iter().filter(|event| moves || !event.is_move()) {
+                observer.on_collision(event);
+            }
+        }
+    }
 
-        let len = self.universe.area.len();
-        let num_threads = current_num_threads();
-        let slice_size = len / num_threads;
+    /// Completes the [TickStats] of a tick with the number of particles per [Material], if stats
+    /// are collected.
+    fn finish_stats(&mut self, mut stats: TickStats, parallel: bool) {
+        if !self.collect_stats {
+            return;
+        }
 
-        (0..num_threads).into_par_iter().for_each(|i| {
-            let start = slice_size * i;
+        let count = |mut counts: Vec<usize>, cell: &Cell| {
+            counts[usize::from(cell.material.id())] += 1;
+            counts
+        };
+        let len = self.materials.len();
+        stats.materials = if parallel {
+            self.universe
+                .area
+                .par_iter()
+                .fold(|| vec![0; len], count)
+                .reduce(
+                    || vec![0; len],
+                    |mut a, b| {
+                        a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                        a
+                    },
+                )
+        } else {
+            self.universe.area.iter().fold(vec![0; len], count)
+        };
 
-            // we need to have the special case for the last iteration, as the final part for
-            // universe might be bigger than than [slice_size]
-            let end = if i == num_threads - 1 {
-                len - 1
-            } else {
-                slice_size * (i + 1)
-            };
+        self.stats = Some(stats);
+    }
 
-            for index in (start..end).rev() {
-                let pos = self.universe.i_to_pos(index);
-                self.handle_collision(&pos, &failed_locks);
-            }
-        });
+    /// Captures the state before the particles of a tick are simulated, if invariants are checked.
+    fn start_check(&self) -> Option<Check> {
+        self.check_invariants
+            .then(|| Check::start(self.ticks, &self.universe, self.chunks.awake()))
+    }
+
+    fn finish_check(&mut self, check: Option<Check>, report: &Report) {
+        if let Some(check) = check {
+            let violations = check.finish(&self.universe, &report.ledger);
+            self.violations.extend(violations);
+        }
+    }
+
+    /// Returns a copy of the area before a tick, if the [History] records ticks.
+    fn start_recording_tick(&mut self) -> Option<Vec<Cell>> {
+        if !self.history.records_ticks() {
+            self.history.settle(&self.universe.area);
+            return None;
+        }
+
+        // pending edits happened before the tick, so they have to be undone after it
+        self.commit_edit();
+        Some(self.universe.area.clone())
+    }
+
+    fn finish_recording_tick(&mut self, before: Option<Vec<Cell>>) {
+        if let Some(before) = before {
+            self.history.record_tick(before, &self.universe.area);
+        }
+    }
+
+    fn simulate(&mut self) -> Report {
+        let observe = self.observe();
+        let check = self.check_invariants;
+        let rng = SimRng::seed_from_u64(self.rng.gen());
+        let region = Region {
+            x: 0..self.universe.width,
+            y: 0..self.universe.height,
+        };
+        let area = SharedArea::new(&mut self.universe);
 
-        let x = *failed_locks.lock().unwrap();
-        x
+        Stepper::new(
+            &area,
+            &self.materials,
+            &self.chunks,
+            region,
+            rng,
+            observe,
+            check,
+        )
+        .simulate(self.chunks.awake_positions_rev())
+    }
+
+    /// Simulates the chunks of the [Universe] phase by phase, with the chunks of each phase being
+    /// simulated in parallel.
+    fn par_simulate(&mut self) -> Report {
+        let phases: Vec<Vec<Region>> = (0..4).map(|phase| self.chunks.phase(phase)).collect();
+
+        // every chunk gets its own rng, so the outcome does not depend on the scheduling of threads
+        let seeds: Vec<Vec<u64>> = phases
+            .iter()
+            .map(|chunks| chunks.iter().map(|_| self.rng.gen()).collect())
+            .collect();
+
+        let observe = self.observe();
+        let check = self.check_invariants;
+        let area = SharedArea::new(&mut self.universe);
+        let mut report = Report::default();
+
+        for (chunks, seeds) in phases.into_iter().zip(seeds) {
+            report += chunks
+                .into_par_iter()
+                .zip(seeds)
+                .map(|(region, seed)| {
+                    let positions = region.positions_rev();
+                    let rng = SimRng::seed_from_u64(seed);
+                    Stepper::new(
+                        &area,
+                        &self.materials,
+                        &self.chunks,
+                        region,
+                        rng,
+                        observe,
+                        check,
+                    )
+                    .simulate(positions)
+                })
+                .reduce(Report::default, |mut a, b| {
+                    a += b;
+                    a
+                });
+        }
+
+        report
+    }
+
+    /// Conducts heat between neighboring cells of all awake chunks.
+    ///
+    /// Heat is exchanged in place, chunk by chunk, so the phases of the chunks are used to get the
+    /// same result as [Simulation::par_conduct_heat].
+    fn conduct_heat(&mut self) {
+        let phases: Vec<Vec<Region>> = (0..4).map(|phase| self.chunks.phase(phase)).collect();
+        let area = SharedArea::new(&mut self.universe);
+
+        for region in phases.iter().flatten() {
+            heat::conduct(&area, &self.materials, region, |pos| {
+                self.chunks.mark_changed(pos)
+            });
+        }
+    }
+
+    /// Conducts heat between neighboring cells of all awake chunks. Uses multithreading where possible.
+    fn par_conduct_heat(&mut self) {
+        let phases: Vec<Vec<Region>> = (0..4).map(|phase| self.chunks.phase(phase)).collect();
+        let area = SharedArea::new(&mut self.universe);
+
+        for chunks in phases {
+            chunks.into_par_iter().for_each(|region| {
+                heat::conduct(&area, &self.materials, &region, |pos| {
+                    self.chunks.mark_changed(pos)
+                });
+            });
+        }
     }
 
     /// Fills (part of) the universe of the simulation with the given area.
-    pub fn fill(&self, area: &[Material]) {
+    ///
+    /// # Panics
+    /// If the area is larger than the universe or contains unknown materials, see
+    /// [Simulation::try_fill].
+    pub fn fill(&mut self, area: &[Material]) {
+        if let Err(e) = self.try_fill(area) {
+            panic!("{e}");
+        }
+    }
+
+    /// Fills (part of) the universe of the simulation with the given area, or fails if the area is
+    /// larger than the universe or contains unknown materials.
+    pub fn try_fill(&mut self, area: &[Material]) -> Result<(), SimError> {
+        self.check_fill(area)?;
+        self.record_fill(area.len());
         for (i, kind) in area.iter().enumerate() {
-            *self.universe.area[i].lock().unwrap() = Particle::new(kind.clone(), false, 0);
+            self.universe.area[i] = self.materials.particle(*kind);
         }
+        self.chunks.mark_all_changed();
+
+        Ok(())
     }
 
     /// Fills (part of) the universe of the simulation with the given area. Uses multithreading where possible.
-    pub fn par_fill(&self, area: &[Material]) {
-        area.par_iter().enumerate().for_each(|(i, kind)| {
-            *self.universe.area[i].lock().unwrap() = Particle::new(kind.clone(), false, 0);
-        });
+    ///
+    /// # Panics
+    /// If the area is larger than the universe or contains unknown materials, see
+    /// [Simulation::try_par_fill].
+    pub fn par_fill(&mut self, area: &[Material]) {
+        if let Err(e) = self.try_par_fill(area) {
+            panic!("{e}");
+        }
+    }
+
+    /// Like [Simulation::try_fill], but uses multithreading where possible.
+    pub fn try_par_fill(&mut self, area: &[Material]) -> Result<(), SimError> {
+        self.check_fill(area)?;
+        self.record_fill(area.len());
+        let materials = &self.materials;
+        self.universe.area[..area.len()]
+            .par_iter_mut()
+            .zip(area)
+            .for_each(|(cell, kind)| *cell = materials.particle(*kind));
+        self.chunks.mark_all_changed();
+
+        Ok(())
+    }
+
+    fn check_fill(&self, area: &[Material]) -> Result<(), SimError> {
+        let size = self.universe.area.len();
+        if area.len() > size {
+            return Err(SimError::AreaTooLarge {
+                len: area.len(),
+                size,
+            });
+        }
+
+        for material in area {
+            self.check_material(*material)?;
+        }
+
+        Ok(())
+    }
+
+    fn record_fill(&mut self, len: usize) {
+        for (i, cell) in self.universe.area[..len].iter().enumerate() {
+            self.history.record_edit(i, cell);
+        }
     }
 
     /// Sets all [Particle] in the [Universe] to unhandled.
-    pub fn set_all_unhandled(&self) {
-        for cell in &self.universe.area {
-            cell.lock().unwrap().handled = false;
+    pub fn set_all_unhandled(&mut self) {
+        for cell in &mut self.universe.area {
+            cell.handled = false;
         }
     }
 
     /// Sets all [Particle] in the [Universe] to unhandled. Uses multithreading where possible.
-    pub fn par_set_all_unhandled(&self) {
+    pub fn par_set_all_unhandled(&mut self) {
         self.universe
             .area
-            .par_iter()
-            .for_each(|cell| cell.lock().unwrap().handled = false);
+            .par_iter_mut()
+            .for_each(|cell| cell.handled = false);
+    }
+}
+
+/// The share of its speed a [Particle] keeps when it is deflected into a fallback direction.
+const DEFLECTION_DAMPING: f32 = 0.5;
+
+/// The speed from which on a [Particle] passes momentum on to the neighbor that blocks its way.
+///
+/// Particles at rest are accelerated by gravity every tick, which must not push on the particles
+/// below them.
+const IMPACT_SPEED: f32 = 2.0;
+
+/// The share of its momentum a [Particle] passes on to the neighbor that blocks its way.
+const MOMENTUM_TRANSFER: f32 = 0.5;
+
+/// Handles the collisions of all [Particle]s that start a tick within a [Region] of a [Universe].
+///
+/// Particles may leave the region, but never further than [MAX_VELOCITY] plus one cell.
+///
+/// Particles are moved iteratively, so their speed has no influence on the depth of the stack.
+/// A particle that is swapped into the place of a moving one is not handled again during the
+/// same tick.
+struct Stepper<'a> {
+    area: &'a SharedArea<'a>,
+    materials: &'a MaterialRegistry,
+    chunks: &'a Chunks,
+    region: Region,
+    rng: SimRng,
+    observe: Observe,
+    /// Whether to record a [Ledger] to check the invariants of the tick.
+    check: bool,
+    report: Report,
+}
+
+/// Which [CollisionEvent]s a [Stepper] collects for the observers of a [Simulation].
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Observe {
+    Nothing,
+    Reactions,
+    Everything,
+}
+
+/// What a [Stepper] found out while simulating its cells.
+#[derive(Default)]
+struct Report {
+    stats: TickStats,
+    events: Vec<CollisionEvent>,
+    ledger: Ledger,
+}
+
+impl AddAssign for Report {
+    fn add_assign(&mut self, rhs: Self) {
+        self.stats += rhs.stats;
+        self.events.extend(rhs.events);
+        self.ledger += rhs.ledger;
+    }
+}
+
+impl<'a> Stepper<'a> {
+    fn new(
+        area: &'a SharedArea<'a>,
+        materials: &'a MaterialRegistry,
+        chunks: &'a Chunks,
+        region: Region,
+        rng: SimRng,
+        observe: Observe,
+        check: bool,
+    ) -> Self {
+        Self {
+            area,
+            materials,
+            chunks,
+            region,
+            rng,
+            observe,
+            check,
+            report: Report::default(),
+        }
+    }
+
+    /// Simulates the cells at the given positions and returns a [Report] of doing so.
+    fn simulate(mut self, positions: impl Iterator<Item = Position>) -> Report {
+        for pos in positions {
+            self.handle_collision(&pos);
+        }
+
+        self.report
+    }
+
+    /// Returns the cell at the given [Position].
+    fn cell(&self, pos: &Position) -> &'a mut Particle {
+        // SAFETY: a stepper only accesses cells within the reach of its region, and never holds on
+        // to more than one reference per cell
+        unsafe { self.area.get_mut(pos) }
+    }
+
+    /// Returns a new, already handled [Particle] of the given [Material].
+    fn particle(&self, material: Material) -> Particle {
+        let mut particle = self.materials.particle(material);
+        particle.handled = true;
+
+        particle
+    }
+
+    /// Marks the chunks around one or two changed cells, so they are simulated during the next tick.
+    fn mark_changed(&self, pos: &Position, other: Option<&Position>) {
+        self.chunks.mark_changed(pos);
+        if let Some(other) = other {
+            self.chunks.mark_changed(other);
+        }
+    }
+
+    /// Ages the particle at the given [Position], and lets it decay once it is old enough.
+    ///
+    /// Takes the particle the caller already holds, so that there is only one reference to it.
+    fn age(&mut self, pos: &Position, cell_content: &mut Particle) {
+        let Some(decay) = self.materials.get(cell_content.material).decay() else {
+            return;
+        };
+
+        cell_content.age = cell_content.age.saturating_add(1);
+        if decay.is_due(cell_content.age, &mut self.rng) {
+            self.record(cell_content.material, decay.into);
+            *cell_content = self.materials.particle(decay.into);
+        }
+
+        // aging particles keep their chunk awake, so they still decay when they do not move
+        self.mark_changed(pos, None);
     }
 
     /// Handles collisions for a cell in a [Universe] at the given [Position].
-    fn handle_collision(&self, pos: &Position, failed_locks: &Mutex<usize>) {
-        let mut cell_content = self.universe.get_cell(pos).unwrap().lock().unwrap();
+    fn handle_collision(&mut self, pos: &Position) {
+        // particles that were swapped out of the region are left to the stepper of their new
+        // region, so that the reach of this stepper stays bounded
+        if !self.region.contains(pos) {
+            return;
+        }
+
+        if self.check {
+            self.report.ledger.scan(pos);
+        }
+
+        let cell_content = self.cell(pos);
 
         if cell_content.handled {
             return;
         }
 
-        cell_content.velocity += 1;
-        let steps_remaining = cell_content.velocity.abs();
+        if self.check {
+            self.report.ledger.simulate();
+        }
+        self.age(pos, cell_content);
+
+        let definition = self.materials.get(cell_content.material);
+        if let Some(material) = definition.transition_at(cell_content.temperature) {
+            self.record(cell_content.material, material);
+            cell_content.material = material;
+            cell_content.age = 0;
+            self.mark_changed(pos, None);
+        }
+
+        let definition = self.materials.get(cell_content.material);
+        let max_speed = definition.terminal_velocity().min(MAX_VELOCITY);
+        cell_content.velocity =
+            (cell_content.velocity + Velocity::new(0.0, definition.gravity())).limit(max_speed);
+        // every particle gets at least one step, so that particles at rest can start moving
+        let steps = (cell_content.velocity.speed().round() as u16).max(1);
+
+        let swaps = self.report.stats.outcomes.swaps();
+        self.travel(pos, steps);
+        if self.report.stats.outcomes.swaps() > swaps {
+            self.report.stats.moved += 1;
+        }
+    }
+
+    /// Moves a cell along the line of its velocity, one neighbor at a time, until it is stopped or
+    /// has used up its steps.
+    fn travel(&mut self, pos: &Position, steps: u16) {
+        let mut pos = pos.clone();
+        let mut line = Line::new(self.cell(&pos).velocity);
+
+        for _ in 0..steps {
+            match self.step(&pos, line.next()) {
+                Some(next) => pos = next,
+                None => return,
+            }
+        }
 
-        self.step(pos, cell_content, steps_remaining, failed_locks);
+        // we used all steps without stopping, i.e. free fall
+        self.cell(&pos).handled = true;
     }
 
     /// Calculates a step during collision handling of a cell in a [Universe].
     ///
-    /// A cell might want to collide multiple times, based on its velocity. This function recursively
-    /// calls itself until satisfied.
-    fn step(
-        &self,
-        pos: &Position,
-        mut cell_content: MutexGuard<Particle>,
-        steps_remaining: i16,
-        failed_locks: &Mutex<usize>,
-    ) {
-        if steps_remaining == 0 {
-            // we used all steps without stopping, i.e. free fall
-            cell_content.handled = true;
-            return;
-        }
+    /// A cell first tries to move to the next cell on its line, and falls back to the directions
+    /// of its material if it is blocked. Moving in a fallback direction deflects its velocity into
+    /// that direction.
+    ///
+    /// Returns the new position of the cell if it moved along its line and may keep moving, or
+    /// [None] if it is done for this tick.
+    fn step(&mut self, pos: &Position, preferred: Option<Direction>) -> Option<Position> {
+        let definition = self.materials.get(self.cell(pos).material);
+        let fallbacks = ExtDirIterator::new(definition.directions(), &mut self.rng)
+            .filter(|dir| Some(*dir) != preferred.as_ref());
 
-        let dirs = cell_content.material.directions();
-
-        for dir in ExtDirIterator::new(&dirs) {
-            if let Some((neighbor_pos, neighbor)) = self.universe.get_neighbor(pos, dir) {
-                // we cannot `neighbor.lock()` here as this might cause a deadlock.
-                // therefore we just `try_lock()` and move on to the next neighbor if it fails
-                let Ok(mut neighbor_content) = neighbor.try_lock() else {
-                    println!("Failed to acquire lock for neighbor at {neighbor_pos:?}");
-                    *failed_locks.lock().unwrap() += 1;
-                    continue;
-                };
-
-                // TODO: surely there must be a more elegant way for this instead of having multiple `breaks`, one `return` and one case of recursion
-                match cell_content
-                    .material
-                    .collide(&neighbor_content.material, dir)
-                {
-                    SwapAndMove => {
-                        let copy = cell_content.clone();
-                        *cell_content = neighbor_content.clone();
-                        *neighbor_content = copy;
-
-                        drop(cell_content);
-                        self.step(
-                            &neighbor_pos,
-                            neighbor_content,
-                            steps_remaining - 1,
-                            failed_locks,
-                        );
-                        return self.handle_collision(pos, failed_locks);
-                    }
-                    SwapAndStop => {
-                        cell_content.velocity = 0;
+        for dir in preferred.iter().chain(fallbacks) {
+            let neighbor_pos = match self.area.universe().neighbor(pos, dir) {
+                Neighbor::Cell(neighbor_pos) => neighbor_pos,
+                Neighbor::Wall => continue,
+                Neighbor::Void if self.void(pos) => return None,
+                Neighbor::Void => continue,
+            };
 
-                        let copy = cell_content.clone();
-                        *cell_content = neighbor_content.clone();
-                        *neighbor_content = copy;
+            let cell_content = self.cell(pos);
+            let neighbor_content = self.cell(&neighbor_pos);
 
-                        drop(cell_content);
-                        self.step(&neighbor_pos, neighbor_content, 0, failed_locks);
-                        return self.handle_collision(pos, failed_locks);
-                    }
-                    Convert(replace_material) => {
-                        *neighbor_content = Particle::new(replace_material, true, 0);
+            // TODO: surely there must be a more elegant way for this instead of having multiple `breaks` and `returns`
+            let desire = self.materials.collide(
+                cell_content.material,
+                neighbor_content.material,
+                dir,
+                &mut self.rng,
+            );
+            self.report.stats.outcomes.count(&desire);
+            let before = (cell_content.material, neighbor_content.material);
+            if self.check {
+                self.report.ledger.collide(before, &desire);
+            }
 
-                        break;
+            match desire {
+                SwapAndMove => {
+                    let deflected = Some(dir) != preferred.as_ref();
+                    if deflected {
+                        let speed = cell_content.velocity.speed() * DEFLECTION_DAMPING;
+                        cell_content.velocity = Velocity::along(dir, speed);
+                        // the cell follows its new line during the next tick
+                        cell_content.handled = true;
                     }
-                    Evade => {}
-                    Consume(mat) => {
-                        *neighbor_content = cell_content.clone();
-                        *cell_content = Particle::new(mat, true, 0);
+                    mem::swap(cell_content, neighbor_content);
+                    // the displaced neighbor is not simulated again during this tick
+                    self.record_displaced(cell_content.handled);
+                    cell_content.handled = true;
+                    self.mark_changed(pos, Some(&neighbor_pos));
+                    self.observe(
+                        pos,
+                        &neighbor_pos,
+                        before,
+                        (cell_content.material, neighbor_content.material),
+                        &desire,
+                    );
 
-                        break;
-                    }
-                    GetConverted(mat) => {
-                        *cell_content = Particle::new(mat, true, 0);
+                    return (!deflected).then_some(neighbor_pos);
+                }
+                SwapAndStop => {
+                    cell_content.velocity = Velocity::ZERO;
+                    cell_content.handled = true;
+                    mem::swap(cell_content, neighbor_content);
+                    self.record_displaced(cell_content.handled);
+                    cell_content.handled = true;
+                    self.mark_changed(pos, Some(&neighbor_pos));
+                    self.observe(
+                        pos,
+                        &neighbor_pos,
+                        before,
+                        (cell_content.material, neighbor_content.material),
+                        &desire,
+                    );
 
-                        break;
-                    }
-                    Eradicate(new_current_mat, new_neighbor_mat) => {
-                        *cell_content = Particle::new(new_current_mat, true, 0);
-                        *neighbor_content = Particle::new(new_neighbor_mat, true, 0);
+                    return None;
+                }
+                Convert(replace_material) => {
+                    self.record_displaced(neighbor_content.handled);
+                    *neighbor_content = self.particle(replace_material);
+                    self.mark_changed(&neighbor_pos, None);
+                    self.observe(
+                        pos,
+                        &neighbor_pos,
+                        before,
+                        (cell_content.material, neighbor_content.material),
+                        &desire,
+                    );
 
-                        break;
+                    break;
+                }
+                Evade => {
+                    if Some(dir) == preferred.as_ref() {
+                        self.transfer_momentum(cell_content, neighbor_content, &neighbor_pos);
                     }
                 }
+                Consume(mat) => {
+                    // the cell moves on to the place of its neighbor, where it must not be
+                    // simulated again
+                    cell_content.handled = true;
+                    self.record_displaced(neighbor_content.handled);
+                    *neighbor_content = cell_content.clone();
+                    *cell_content = self.particle(mat);
+                    self.mark_changed(pos, Some(&neighbor_pos));
+                    self.observe(
+                        pos,
+                        &neighbor_pos,
+                        before,
+                        (cell_content.material, neighbor_content.material),
+                        &desire,
+                    );
+
+                    break;
+                }
+                GetConverted(mat) => {
+                    *cell_content = self.particle(mat);
+                    self.mark_changed(pos, None);
+                    self.observe(
+                        pos,
+                        &neighbor_pos,
+                        before,
+                        (cell_content.material, neighbor_content.material),
+                        &desire,
+                    );
+
+                    break;
+                }
+                Eradicate(new_current_mat, new_neighbor_mat) => {
+                    self.record_displaced(neighbor_content.handled);
+                    *cell_content = self.particle(new_current_mat);
+                    *neighbor_content = self.particle(new_neighbor_mat);
+                    self.mark_changed(pos, Some(&neighbor_pos));
+                    self.observe(
+                        pos,
+                        &neighbor_pos,
+                        before,
+                        (cell_content.material, neighbor_content.material),
+                        &desire,
+                    );
+
+                    break;
+                }
             }
         }
+
         // we checked all neighbors and couldnt move, so we save cell with velocity = 0
-        cell_content.velocity = 0;
+        let cell_content = self.cell(pos);
+        cell_content.velocity = Velocity::ZERO;
         cell_content.handled = true;
+
+        None
+    }
+
+    /// Deletes the particle at the given [Position], which crossed a void edge of the [Universe].
+    /// Returns whether there was a particle, i.e. the cell was not empty.
+    fn void(&mut self, pos: &Position) -> bool {
+        let cell_content = self.cell(pos);
+        if cell_content.material == Material::AIR {
+            return false;
+        }
+
+        self.record(cell_content.material, Material::AIR);
+        *cell_content = self.particle(Material::AIR);
+        self.report.stats.voided += 1;
+        self.mark_changed(pos, None);
+
+        true
+    }
+
+    /// Records that a particle was displaced or replaced, unless it was handled already, if the
+    /// invariants of the tick are checked.
+    fn record_displaced(&mut self, handled: bool) {
+        if self.check && !handled {
+            self.report.ledger.displace();
+        }
+    }
+
+    /// Records that a particle changed its [Material], if the invariants of the tick are checked.
+    fn record(&mut self, from: Material, to: Material) {
+        if self.check {
+            self.report.ledger.convert(from, to);
+        }
+    }
+
+    /// Reports a collision to the observers of the simulation, if they are interested in it.
+    fn observe(
+        &mut self,
+        pos: &Position,
+        neighbor_pos: &Position,
+        before: (Material, Material),
+        after: (Material, Material),
+        outcome: &CollisionDesire,
+    ) {
+        let event = CollisionEvent {
+            pos: pos.clone(),
+            neighbor_pos: neighbor_pos.clone(),
+            before,
+            after,
+            outcome: outcome.clone(),
+        };
+
+        match self.observe {
+            Observe::Everything => self.report.events.push(event),
+            Observe::Reactions if !event.is_move() => self.report.events.push(event),
+            _ => (),
+        }
+    }
+
+    /// Passes part of the momentum of a fast cell on to a movable neighbor that blocks its way.
+    fn transfer_momentum(
+        &self,
+        cell: &mut Particle,
+        neighbor: &mut Particle,
+        neighbor_pos: &Position,
+    ) {
+        if cell.velocity.speed() < IMPACT_SPEED
+            || !self.materials.get(neighbor.material).state().is_movable()
+        {
+            return;
+        }
+
+        let transferred = cell.velocity * MOMENTUM_TRANSFER;
+        neighbor.velocity = (neighbor.velocity + transferred).limit(MAX_VELOCITY);
+        cell.velocity = cell.velocity - transferred;
+        self.mark_changed(neighbor_pos, None);
+    }
+}
+
+/// Traces the cells on a straight line along a [Velocity], one neighbor at a time.
+///
+/// Every step goes one cell along the faster axis of the velocity, and one cell along the slower
+/// axis whenever the line has moved closer to the next cell on that axis.
+struct Line {
+    /// The offset per step, with the faster axis being 1 or -1.
+    delta: (f32, f32),
+    /// The exact offset of the line from its start.
+    exact: (f32, f32),
+    /// The offset of the current cell from the start.
+    cell: (isize, isize),
+}
+
+impl Line {
+    fn new(velocity: Velocity) -> Self {
+        let speed = velocity.speed();
+        // slow cells don't follow a line, they only use the directions of their material
+        let delta = if speed < 0.5 {
+            (0.0, 0.0)
+        } else {
+            (velocity.x / speed, velocity.y / speed)
+        };
+
+        Self {
+            delta,
+            exact: (0.0, 0.0),
+            cell: (0, 0),
+        }
+    }
+}
+
+impl Iterator for Line {
+    type Item = Direction;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.exact = (self.exact.0 + self.delta.0, self.exact.1 + self.delta.1);
+        let next = (self.exact.0.round() as isize, self.exact.1.round() as isize);
+        let dir = Direction::from_offset(next.0 - self.cell.0, next.1 - self.cell.1);
+        self.cell = next;
+
+        dir
+    }
+}
+
+/// Returns a [SimError::InvalidSize] if a [Universe] of the given size would have more than
+/// [MAX_CELLS] cells.
+pub(crate) fn check_size(width: usize, height: usize) -> Result<(), SimError> {
+    match width.checked_mul(height) {
+        Some(cells) if cells <= MAX_CELLS => Ok(()),
+        _ => Err(SimError::InvalidSize { width, height }),
+    }
+}
+
+/// An error caused by invalid input to a [Simulation].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimError {
+    /// A position is outside of the universe.
+    OutOfBounds {
+        pos: Position,
+        width: usize,
+        height: usize,
+    },
+    /// An area with more cells than the universe was used to fill it.
+    AreaTooLarge { len: usize, size: usize },
+    /// A material is not registered with the simulation.
+    UnknownMaterial(Material),
+    /// No material with the given name is registered with the simulation.
+    UnknownMaterialName(String),
+    /// A universe of this size would have more than [MAX_CELLS] cells.
+    InvalidSize { width: usize, height: usize },
+}
+
+impl Display for SimError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfBounds { pos, width, height } => write!(
+                f,
+                "position ({}, {}) is outside of the universe of size {width}x{height}",
+                pos.x, pos.y
+            ),
+            Self::AreaTooLarge { len, size } => write!(
+                f,
+                "area of {len} cells does not fit into the universe of {size} cells"
+            ),
+            Self::UnknownMaterial(material) => {
+                write!(f, "unknown material with id {}", material.id())
+            }
+            Self::UnknownMaterialName(name) => write!(f, "unknown material '{name}'"),
+            Self::InvalidSize { width, height } => write!(
+                f,
+                "universe of size {width}x{height} has more than {MAX_CELLS} cells"
+            ),
+        }
+    }
+}
+
+impl Error for SimError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::OutcomeCounts;
+    use crate::universe::Boundary;
+
+    /// Returns a busy scene of the built-in materials.
+    fn busy(width: usize, height: usize) -> Vec<Material> {
+        let materials = [
+            Material::AIR,
+            Material::SAND,
+            Material::WATER,
+            Material::FIRE,
+            Material::WOOD,
+        ];
+        (0..width * height)
+            .map(|i| materials[i * 7 % 11 % materials.len()])
+            .collect()
+    }
+
+    #[test]
+    fn simulations_with_the_same_seed_are_identical() {
+        let run = |seed| {
+            let mut sim = Simulation::with_seed(60, 40, seed);
+            sim.fill(&busy(60, 40));
+            for _ in 0..20 {
+                sim.tick();
+            }
+            sim.universe.area
+        };
+
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+    }
+
+    #[test]
+    fn par_tick_keeps_the_invariants() {
+        let mut sim = Simulation::with_seed(150, 100, 0);
+        sim.fill(&busy(150, 100));
+        sim.set_check_invariants(true);
+        for _ in 0..30 {
+            sim.par_tick();
+        }
+
+        assert_eq!(sim.take_violations(), Vec::new());
+    }
+
+    #[test]
+    fn par_tick_does_not_depend_on_the_number_of_threads() {
+        let run = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut sim = Simulation::with_seed(150, 100, 1);
+                sim.fill(&busy(150, 100));
+                for _ in 0..30 {
+                    sim.par_tick();
+                }
+                sim.universe.area
+            })
+        };
+
+        assert_eq!(run(1), run(4));
+    }
+
+    #[test]
+    fn stats_count_a_falling_grain_of_sand() {
+        let mut sim = Simulation::with_seed(3, 5, 0);
+        let mut area = vec![Material::AIR; 3 * 5];
+        area[1] = Material::SAND;
+        sim.fill(&area);
+        sim.tick();
+        assert_eq!(sim.stats(), None);
+
+        sim.set_collect_stats(true);
+        sim.tick();
+        let stats = sim.stats().unwrap();
+        assert_eq!(sim.universe.area[3 * 3 + 1].material, Material::SAND);
+        assert_eq!(stats.count(Material::SAND), 1);
+        assert_eq!(stats.count(Material::AIR), 14);
+        assert_eq!(stats.count(Material::WATER), 0);
+        assert_eq!(stats.moved, 1);
+        assert_eq!(
+            stats.outcomes,
+            OutcomeCounts {
+                swap_and_move: 2,
+                ..OutcomeCounts::default()
+            }
+        );
+
+        sim.set_collect_stats(false);
+        assert_eq!(sim.stats(), None);
+    }
+
+    fn materials(sim: &Simulation) -> Vec<Material> {
+        sim.universe()
+            .area
+            .iter()
+            .map(|cell| cell.material)
+            .collect()
+    }
+
+    #[test]
+    fn flood_fill_stops_at_other_materials() {
+        let mut sim = Simulation::with_seed(5, 3, 0);
+        let (from, to) = (Position::new(2, 0), Position::new(2, 2));
+        sim.paint_rect(&from, &to, Material::WOOD).unwrap();
+        sim.paint(&Position::new(4, 1), Material::SAND).unwrap();
+
+        sim.flood_fill(&Position::new(0, 1), Material::WATER)
+            .unwrap();
+
+        let (air, sand, wood, water) = (
+            Material::AIR,
+            Material::SAND,
+            Material::WOOD,
+            Material::WATER,
+        );
+        #[rustfmt::skip]
+        let expected = [
+            water, water, wood, air, air,
+            water, water, wood, air, sand,
+            water, water, wood, air, air,
+        ];
+        assert_eq!(materials(&sim), expected);
+    }
+
+    #[test]
+    fn thick_lines_are_clipped_to_the_universe() {
+        let mut sim = Simulation::with_seed(6, 4, 0);
+        let (from, to) = (Position::new(0, 0), Position::new(5, 0));
+        sim.paint_line(&from, &to, 3, Material::WOOD).unwrap();
+
+        // the line reaches one and a half cells to each side, but only two rows are left
+        let wood = materials(&sim)
+            .iter()
+            .enumerate()
+            .filter(|(_, &material)| material == Material::WOOD)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(wood, (0..12).collect::<Vec<_>>());
+    }
+
+    /// Returns the positions of all particles of a [Material].
+    fn positions(sim: &Simulation, material: Material) -> Vec<Position> {
+        let universe = sim.universe();
+        (0..universe.area.len())
+            .filter(|&i| universe.area[i].material == material)
+            .map(|i| universe.i_to_pos(i))
+            .collect()
+    }
+
+    #[test]
+    fn resizing_keeps_particles_at_the_anchor() {
+        for (anchor, grown, shrunk) in [
+            (Anchor::TopLeft, Some(Position::new(1, 2)), None),
+            (
+                Anchor::Center,
+                Some(Position::new(3, 3)),
+                Some(Position::new(0, 1)),
+            ),
+            (
+                Anchor::Bottom,
+                Some(Position::new(3, 4)),
+                Some(Position::new(0, 0)),
+            ),
+        ] {
+            let mut sim = Simulation::with_seed(4, 4, 0);
+            let pos = Position::new(1, 2);
+            sim.paint(&pos, Material::WOOD).unwrap();
+
+            sim.resize(8, 6, anchor).unwrap();
+            assert_eq!(positions(&sim, Material::WOOD), Vec::from_iter(grown));
+            assert_eq!(sim.universe().area.len(), 8 * 6);
+
+            sim.resize(4, 4, anchor).unwrap();
+            assert_eq!(positions(&sim, Material::WOOD), vec![pos.clone()]);
+
+            // particles that end up outside of the universe are removed
+            sim.resize(2, 2, anchor).unwrap();
+            assert_eq!(positions(&sim, Material::WOOD), Vec::from_iter(shrunk));
+        }
+    }
+
+    #[test]
+    fn resizing_clears_the_history() {
+        let mut sim = Simulation::with_seed(4, 4, 0);
+        sim.set_history(History::new(10, true));
+        sim.paint(&Position::new(1, 1), Material::WOOD).unwrap();
+        sim.commit_edit();
+        assert!(sim.history().can_undo());
+
+        sim.resize(6, 6, Anchor::Center).unwrap();
+
+        assert!(!sim.history().can_undo());
+        assert!(!sim.undo());
+        assert_eq!(sim.history().capacity(), 10);
+        assert!(sim.history().records_ticks());
+    }
+
+    #[test]
+    fn resizing_beyond_the_maximum_size_fails() {
+        let mut sim = Simulation::with_seed(4, 4, 0);
+        for (width, height) in [(usize::MAX, 2), (MAX_CELLS + 1, 1)] {
+            assert_eq!(
+                sim.resize(width, height, Anchor::TopLeft),
+                Err(SimError::InvalidSize { width, height })
+            );
+        }
+
+        assert_eq!((sim.universe().width, sim.universe().height), (4, 4));
+    }
+
+    #[test]
+    fn positions_outside_of_the_universe_are_out_of_bounds() {
+        let mut sim = Simulation::with_seed(4, 3, 0);
+        let pos = Position::new(4, 1);
+        let error = SimError::OutOfBounds {
+            pos: pos.clone(),
+            width: 4,
+            height: 3,
+        };
+
+        assert_eq!(sim.try_get_cell(&pos), Err(error.clone()));
+        assert_eq!(sim.try_get_cell_mut(&pos), Err(error.clone()));
+        assert_eq!(sim.paint(&pos, Material::SAND), Err(error));
+        assert!(sim.try_get_cell(&Position::new(3, 2)).is_ok());
+    }
+
+    #[test]
+    fn areas_larger_than_the_universe_are_rejected() {
+        let mut sim = Simulation::with_seed(4, 3, 0);
+        let area = vec![Material::SAND; 13];
+        let error = SimError::AreaTooLarge { len: 13, size: 12 };
+
+        assert_eq!(sim.try_fill(&area), Err(error.clone()));
+        assert_eq!(sim.try_par_fill(&area), Err(error));
+        assert!(materials(&sim)
+            .iter()
+            .all(|&material| material == Material::AIR));
+
+        sim.try_fill(&area[..12]).unwrap();
+        sim.try_par_fill(&area[..12]).unwrap();
+    }
+
+    #[test]
+    fn unregistered_materials_are_rejected() {
+        let mut sim = Simulation::with_seed(4, 3, 0);
+        let unknown = Material::from_id(sim.materials().len() as u16);
+        let error = SimError::UnknownMaterial(unknown);
+
+        assert_eq!(sim.paint(&Position::new(1, 1), unknown), Err(error.clone()));
+        assert_eq!(sim.try_fill(&[Material::SAND, unknown]), Err(error.clone()));
+        let (from, to) = (Position::new(0, 0), Position::new(3, 2));
+        assert_eq!(sim.paint_rect(&from, &to, unknown), Err(error));
+        assert!(materials(&sim)
+            .iter()
+            .all(|&material| material == Material::AIR));
+    }
+
+    #[test]
+    fn unknown_material_names_are_rejected() {
+        let sim = Simulation::with_seed(4, 3, 0);
+
+        assert_eq!(sim.find_material("sand"), Ok(Material::SAND));
+        assert_eq!(
+            sim.find_material("Lava"),
+            Err(SimError::UnknownMaterialName("Lava".to_string()))
+        );
+    }
+
+    /// A solid material that turns into air after three ticks.
+    const ROCK: &str = r#"
+[[material]]
+name = "Rock"
+color = [0x80, 0x80, 0x80, 0xff]
+decay = { after = 3, into = "Air" }
+"#;
+
+    #[test]
+    fn particles_decay_once_they_are_old_enough() {
+        let mut materials = MaterialRegistry::builtin();
+        materials.extend_from_toml(ROCK).unwrap();
+        let rock = materials.find("Rock").unwrap();
+
+        let mut sim = Simulation::with_materials(3, 3, 0, materials);
+        let pos = Position { x: 1, y: 2 };
+        sim.paint(&pos, rock).unwrap();
+
+        for age in 1..3 {
+            sim.tick();
+            let cell = sim.try_get_cell(&pos).unwrap();
+            assert_eq!((cell.material, cell.age), (rock, age));
+        }
+
+        sim.tick();
+        assert_eq!(sim.try_get_cell(&pos).unwrap().material, Material::AIR);
+    }
+
+    #[test]
+    fn particles_wrap_around_to_the_opposite_edge() {
+        let mut sim = Simulation::with_seed(3, 4, 0);
+        sim.set_boundaries(Boundaries::all(Boundary::Wrap)).unwrap();
+        let pos = Position { x: 1, y: 3 };
+        sim.paint(&pos, Material::SAND).unwrap();
+
+        sim.tick();
+
+        assert_eq!(sim.try_get_cell(&pos).unwrap().material, Material::AIR);
+        let top = Position { x: 1, y: 0 };
+        assert_eq!(sim.try_get_cell(&top).unwrap().material, Material::SAND);
+    }
+
+    #[test]
+    fn particles_that_cross_a_void_are_deleted() {
+        let mut sim = Simulation::with_seed(3, 4, 0);
+        sim.set_boundaries(Boundaries {
+            bottom: Boundary::Void,
+            ..Boundaries::default()
+        })
+        .unwrap();
+        sim.paint(&Position { x: 1, y: 3 }, Material::SAND).unwrap();
+
+        sim.tick();
+
+        assert_eq!(sim.voided(), 1);
+        let area = &sim.universe().area;
+        assert!(area.iter().all(|cell| cell.material == Material::AIR));
+    }
+
+    #[test]
+    fn wrapping_axes_of_length_one_keep_all_particles() {
+        for (width, height) in [(1, 20), (20, 1), (1, 1)] {
+            let mut sim = Simulation::with_seed(width, height, 0);
+            sim.set_boundaries(Boundaries::all(Boundary::Wrap)).unwrap();
+            sim.set_check_invariants(true);
+            let area = [
+                Material::SAND,
+                Material::WATER,
+                Material::FIRE,
+                Material::AIR,
+            ];
+            let area: Vec<_> = area.into_iter().cycle().take(width * height).collect();
+            sim.fill(&area);
+
+            for _ in 0..10 {
+                sim.tick();
+                sim.par_tick();
+            }
+
+            assert_eq!(sim.take_violations(), Vec::new());
+        }
     }
 }
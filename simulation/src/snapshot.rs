@@ -0,0 +1,425 @@
+//! A versioned binary format for the state of a [Universe], see [Simulation::save].
+//!
+//! All numbers are stored in little endian. A snapshot consists of:
+//!
+//! - the magic bytes [MAGIC] and the format version as `u16`
+//! - the width and height of the universe as `u32`
+//! - the [Boundary] of its left, right, top and bottom edge as `u8`, being 0 for a wall, 1 for
+//!   wrap-around and 2 for a void
+//! - the number of materials as `u16`, followed by their names as `u16` length and UTF-8 bytes.
+//!   Particles refer to materials by their index in this table, so that snapshots stay valid when
+//!   the ids of materials change.
+//! - all particles row by row, each made of its material index as `u16`, its velocity as two
+//!   `f32`, its temperature as `f32`, its age as `u16` and whether it has been handled as `u8`
+//!
+//! [Simulation::save]: crate::sand_sim::Simulation::save
+
+use crate::entities::cell_content::{Particle, Velocity};
+use crate::entities::material::Material;
+use crate::entities::registry::MaterialRegistry;
+use crate::sand_sim::Cell;
+use crate::universe::{Boundaries, Boundary, Universe};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::{fmt, io};
+
+/// The bytes every snapshot starts with.
+pub const MAGIC: [u8; 8] = *b"SANDSPIL";
+
+/// The version of the format written by [write]. Snapshots of older versions can still be read.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The number of cells allocated up front while reading, so that a corrupt header can not make us
+/// allocate huge amounts of memory before running out of data.
+const MAX_PREALLOCATED_CELLS: usize = 1 << 20;
+
+/// Writes the [Universe] of a simulation with the given [MaterialRegistry] as snapshot.
+pub(crate) fn write(
+    writer: &mut impl Write,
+    universe: &Universe<Cell>,
+    materials: &MaterialRegistry,
+) -> Result<(), SnapshotError> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    write_dimension(writer, universe.width)?;
+    write_dimension(writer, universe.height)?;
+    write_boundaries(writer, &universe.boundaries)?;
+
+    write_material_table(writer, materials)?;
+
+    for particle in &universe.area {
+        writer.write_all(&particle.material.id().to_le_bytes())?;
+        writer.write_all(&particle.velocity.x.to_le_bytes())?;
+        writer.write_all(&particle.velocity.y.to_le_bytes())?;
+        writer.write_all(&particle.temperature.to_le_bytes())?;
+        writer.write_all(&particle.age.to_le_bytes())?;
+        writer.write_all(&[u8::from(particle.handled)])?;
+    }
+
+    Ok(())
+}
+
+/// Writes the names of all materials of a [MaterialRegistry], ordered by id.
+pub(crate) fn write_material_table(
+    writer: &mut impl Write,
+    materials: &MaterialRegistry,
+) -> Result<(), SnapshotError> {
+    // the registry never holds more materials than there are ids
+    writer.write_all(&(materials.len() as u16).to_le_bytes())?;
+    for (_, behavior) in materials.materials() {
+        let name = behavior.name().as_bytes();
+        let len = u16::try_from(name.len()).map_err(|_| SnapshotError::NameTooLong)?;
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(name)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the [Boundary] of every edge, in the order left, right, top and bottom.
+pub(crate) fn write_boundaries(writer: &mut impl Write, boundaries: &Boundaries) -> io::Result<()> {
+    let Boundaries {
+        left,
+        right,
+        top,
+        bottom,
+    } = *boundaries;
+    for boundary in [left, right, top, bottom] {
+        let kind = match boundary {
+            Boundary::Wall => 0,
+            Boundary::Wrap => 1,
+            Boundary::Void => 2,
+        };
+        writer.write_all(&[kind])?;
+    }
+
+    Ok(())
+}
+
+fn write_dimension(writer: &mut impl Write, len: usize) -> Result<(), SnapshotError> {
+    let len = u32::try_from(len).map_err(|_| SnapshotError::InvalidDimensions)?;
+    writer.write_all(&len.to_le_bytes())?;
+
+    Ok(())
+}
+
+/// Reads a snapshot and returns its [Universe], with the materials of the snapshot being mapped to
+/// the ones of the given [MaterialRegistry] by name.
+pub(crate) fn read(
+    reader: &mut impl Read,
+    materials: &MaterialRegistry,
+) -> Result<Universe<Cell>, SnapshotError> {
+    if read_array::<8>(reader)? != MAGIC {
+        return Err(SnapshotError::InvalidMagic);
+    }
+
+    let version = read_u16(reader)?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let width = read_u32(reader)? as usize;
+    let height = read_u32(reader)? as usize;
+    let size = width
+        .checked_mul(height)
+        .ok_or(SnapshotError::InvalidDimensions)?;
+    let boundaries = read_boundaries(reader)?;
+    boundaries
+        .validate()
+        .map_err(|_| SnapshotError::InvalidBoundaries)?;
+
+    let table = read_material_table(reader, materials)?;
+
+    let mut area = Vec::with_capacity(size.min(MAX_PREALLOCATED_CELLS));
+    for _ in 0..size {
+        area.push(read_particle(reader, &table)?);
+    }
+
+    Ok(Universe {
+        area,
+        width,
+        height,
+        boundaries,
+    })
+}
+
+/// Reads the boundaries written by [write_boundaries], without checking that they are valid.
+pub(crate) fn read_boundaries(reader: &mut impl Read) -> Result<Boundaries, SnapshotError> {
+    let mut boundaries = [Boundary::Wall; 4];
+    for boundary in &mut boundaries {
+        *boundary = match read_array::<1>(reader)? {
+            [0] => Boundary::Wall,
+            [1] => Boundary::Wrap,
+            [2] => Boundary::Void,
+            _ => return Err(SnapshotError::InvalidBoundaries),
+        };
+    }
+
+    let [left, right, top, bottom] = boundaries;
+
+    Ok(Boundaries {
+        left,
+        right,
+        top,
+        bottom,
+    })
+}
+
+/// Reads the names of materials written by [write_material_table] and maps them to the
+/// [Material]s of the given [MaterialRegistry].
+pub(crate) fn read_material_table(
+    reader: &mut impl Read,
+    materials: &MaterialRegistry,
+) -> Result<Vec<Material>, SnapshotError> {
+    let len = read_u16(reader)?;
+    let mut table = Vec::with_capacity(usize::from(len));
+    for _ in 0..len {
+        let len = read_u16(reader)?;
+        let mut name = vec![0; usize::from(len)];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| SnapshotError::InvalidName)?;
+
+        let material = materials
+            .find(&name)
+            .ok_or(SnapshotError::UnknownMaterial(name))?;
+        table.push(material);
+    }
+
+    Ok(table)
+}
+
+fn read_particle(reader: &mut impl Read, table: &[Material]) -> Result<Particle, SnapshotError> {
+    let index = read_u16(reader)?;
+    let material = *table
+        .get(usize::from(index))
+        .ok_or(SnapshotError::InvalidMaterial(index))?;
+    let velocity = Velocity::new(read_f32(reader)?, read_f32(reader)?);
+    let temperature = read_f32(reader)?;
+    let age = read_u16(reader)?;
+    let handled = match read_array::<1>(reader)? {
+        [0] => false,
+        [1] => true,
+        _ => return Err(SnapshotError::InvalidParticle),
+    };
+
+    if !velocity.x.is_finite() || !velocity.y.is_finite() || !temperature.is_finite() {
+        return Err(SnapshotError::InvalidParticle);
+    }
+
+    Ok(Particle {
+        material,
+        velocity,
+        temperature,
+        age,
+        handled,
+    })
+}
+
+pub(crate) fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+pub(crate) fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    read_array(reader).map(u16::from_le_bytes)
+}
+
+pub(crate) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    read_array(reader).map(u32::from_le_bytes)
+}
+
+pub(crate) fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    read_array(reader).map(u64::from_le_bytes)
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    read_array(reader).map(f32::from_le_bytes)
+}
+
+/// An error that occurred while saving or loading a snapshot.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Reading or writing failed, or the snapshot ended unexpectedly.
+    Io(io::Error),
+    /// The data does not start with [MAGIC], so it is not a snapshot.
+    InvalidMagic,
+    /// The snapshot was written by a newer, or an invalid, version of the format.
+    UnsupportedVersion(u16),
+    /// The universe is too large to be stored in or loaded from a snapshot.
+    InvalidDimensions,
+    /// The snapshot contains an unknown [Boundary], or only one of two opposite edges wraps around.
+    InvalidBoundaries,
+    /// The name of a material is too long to be stored in a snapshot.
+    NameTooLong,
+    /// The name of a material in the snapshot is not valid UTF-8.
+    InvalidName,
+    /// The snapshot contains a material that is not registered with the simulation.
+    UnknownMaterial(String),
+    /// A particle refers to a material that is not part of the material table of the snapshot.
+    InvalidMaterial(u16),
+    /// A particle has an invalid handled flag or a velocity or temperature that is not finite.
+    InvalidParticle,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read or write snapshot: {e}"),
+            Self::InvalidMagic => write!(f, "not a snapshot"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported snapshot version {version}, expected at most {FORMAT_VERSION}"
+            ),
+            Self::InvalidDimensions => write!(f, "snapshot has invalid dimensions"),
+            Self::InvalidBoundaries => write!(f, "snapshot has invalid boundaries"),
+            Self::NameTooLong => write!(f, "material name is too long for a snapshot"),
+            Self::InvalidName => write!(f, "snapshot contains a material name that is not UTF-8"),
+            Self::UnknownMaterial(name) => {
+                write!(f, "snapshot contains unknown material {name}")
+            }
+            Self::InvalidMaterial(index) => {
+                write!(f, "snapshot contains invalid material index {index}")
+            }
+            Self::InvalidParticle => write!(f, "snapshot contains an invalid particle"),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sand_sim::Simulation;
+    use crate::universe::Position;
+
+    const ROCK: &str = "[[material]]\nname = \"Rock\"\ncolor = [0x80, 0x80, 0x80, 0xff]\n";
+    const PEBBLE: &str = "[[material]]\nname = \"Pebble\"\ncolor = [0xa0, 0xa0, 0xa0, 0xff]\n";
+
+    /// Returns a new simulation of a busy scene.
+    fn scene(seed: u64) -> Simulation {
+        let mut sim = Simulation::with_seed(40, 30, seed);
+        sim.set_boundaries(Boundaries {
+            left: Boundary::Wrap,
+            right: Boundary::Wrap,
+            top: Boundary::Wall,
+            bottom: Boundary::Void,
+        })
+        .unwrap();
+        let materials = [
+            Material::SAND,
+            Material::WATER,
+            Material::FIRE,
+            Material::WOOD,
+        ];
+        let area: Vec<_> = (0..40 * 30)
+            .map(|i| materials[i * 7 % 11 % materials.len()])
+            .collect();
+        sim.fill(&area);
+        sim
+    }
+
+    fn save(sim: &Simulation) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        sim.save(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn loaded_snapshots_equal_the_saved_universe() {
+        // after a few ticks, the particles have velocities, temperatures and ages
+        let mut sim = scene(0);
+        for _ in 0..5 {
+            sim.tick();
+        }
+        let bytes = save(&sim);
+
+        let loaded =
+            Simulation::load_with_materials(&mut bytes.as_slice(), 0, MaterialRegistry::builtin())
+                .unwrap();
+        assert_eq!(loaded.universe().width, sim.universe().width);
+        assert_eq!(loaded.universe().height, sim.universe().height);
+        assert_eq!(loaded.universe().boundaries(), sim.universe().boundaries());
+        assert_eq!(loaded.universe().area, sim.universe().area);
+        assert_eq!(save(&loaded), bytes);
+    }
+
+    #[test]
+    fn loaded_snapshots_continue_like_the_original() {
+        let mut sim = scene(7);
+        let bytes = save(&sim);
+        let mut loaded =
+            Simulation::load_with_materials(&mut bytes.as_slice(), 7, MaterialRegistry::builtin())
+                .unwrap();
+
+        for _ in 0..10 {
+            sim.tick();
+            loaded.tick();
+        }
+
+        assert_eq!(loaded.universe().area, sim.universe().area);
+    }
+
+    #[test]
+    fn materials_are_mapped_by_name() {
+        let mut materials = MaterialRegistry::builtin();
+        materials.extend_from_toml(ROCK).unwrap();
+        let rock = materials.find("Rock").unwrap();
+        let particle = materials.particle(rock);
+        let mut sim = Simulation::with_materials(3, 1, 0, materials);
+        *sim.get_cell_mut(&Position { x: 1, y: 0 }).unwrap() = particle;
+        let bytes = save(&sim);
+
+        // the same material gets another id when more materials are registered before it
+        let mut materials = MaterialRegistry::builtin();
+        materials.extend_from_toml(PEBBLE).unwrap();
+        materials.extend_from_toml(ROCK).unwrap();
+        let loaded = read(&mut bytes.as_slice(), &materials).unwrap();
+
+        let moved = materials.find("Rock").unwrap();
+        assert_ne!(moved, rock);
+        assert_eq!(loaded.area[1].material, moved);
+
+        let result = read(&mut bytes.as_slice(), &MaterialRegistry::builtin());
+        assert!(matches!(result, Err(SnapshotError::UnknownMaterial(name)) if name == "Rock"));
+    }
+
+    #[test]
+    fn invalid_snapshots_are_rejected() {
+        let bytes = save(&scene(3));
+        let load = |bytes: &[u8]| read(&mut &bytes[..], &MaterialRegistry::builtin());
+
+        let newer = FORMAT_VERSION + 1;
+        let mut other_version = bytes.clone();
+        other_version[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&newer.to_le_bytes());
+        assert!(matches!(
+            load(&other_version),
+            Err(SnapshotError::UnsupportedVersion(version)) if version == newer
+        ));
+
+        assert!(matches!(
+            load(b"NOTASNAP"),
+            Err(SnapshotError::InvalidMagic)
+        ));
+        assert!(matches!(
+            load(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+//! Statistics about the ticks of a [Simulation], see [Simulation::stats].
+//!
+//! [Simulation]: crate::sand_sim::Simulation
+//! [Simulation::stats]: crate::sand_sim::Simulation::stats
+
+use crate::entities::material::CollisionDesire::{
+    Consume, Convert, Eradicate, Evade, GetConverted, SwapAndMove, SwapAndStop,
+};
+use crate::entities::material::{CollisionDesire, Material};
+use std::ops::AddAssign;
+
+/// Statistics about a single tick of a simulation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TickStats {
+    /// The number of particles of each [Material] after the tick, indexed by the id of the
+    /// material.
+    pub materials: Vec<usize>,
+
+    /// The number of particles that moved to at least one other cell during the tick, not
+    /// counting the particles they displaced.
+    pub moved: usize,
+
+    /// The number of particles that were deleted during the tick because they crossed a void
+    /// edge, see [Simulation::voided].
+    ///
+    /// [Simulation::voided]: crate::sand_sim::Simulation::voided
+    pub voided: usize,
+
+    /// How often each [CollisionDesire] was the outcome of a collision during the tick.
+    pub outcomes: OutcomeCounts,
+}
+
+impl TickStats {
+    /// Returns the number of particles of a [Material] after the tick.
+    pub fn count(&self, material: Material) -> usize {
+        self.materials
+            .get(usize::from(material.id()))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl AddAssign for TickStats {
+    /// Adds up the statistics of two parts of a simulation, e.g. of two chunks.
+    fn add_assign(&mut self, rhs: Self) {
+        if self.materials.len() < rhs.materials.len() {
+            self.materials.resize(rhs.materials.len(), 0);
+        }
+        for (count, other) in self.materials.iter_mut().zip(rhs.materials) {
+            *count += other;
+        }
+        self.moved += rhs.moved;
+        self.voided += rhs.voided;
+        self.outcomes += rhs.outcomes;
+    }
+}
+
+/// The number of collisions per outcome, one field per variant of [CollisionDesire].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutcomeCounts {
+    pub evade: usize,
+    pub swap_and_move: usize,
+    pub swap_and_stop: usize,
+    pub convert: usize,
+    pub consume: usize,
+    pub get_converted: usize,
+    pub eradicate: usize,
+}
+
+impl OutcomeCounts {
+    /// Counts a collision with the given outcome.
+    pub(crate) fn count(&mut self, desire: &CollisionDesire) {
+        let count = match desire {
+            Evade => &mut self.evade,
+            SwapAndMove => &mut self.swap_and_move,
+            SwapAndStop => &mut self.swap_and_stop,
+            Convert(_) => &mut self.convert,
+            Consume(_) => &mut self.consume,
+            GetConverted(_) => &mut self.get_converted,
+            Eradicate(_, _) => &mut self.eradicate,
+        };
+        *count += 1;
+    }
+
+    /// Returns the number of swaps, i.e. of times a particle moved to a neighboring cell.
+    pub fn swaps(&self) -> usize {
+        self.swap_and_move + self.swap_and_stop
+    }
+
+    /// Returns the names of all outcomes with their counts, e.g. to display them.
+    pub fn named(&self) -> [(&'static str, usize); 7] {
+        [
+            ("Evade", self.evade),
+            ("SwapAndMove", self.swap_and_move),
+            ("SwapAndStop", self.swap_and_stop),
+            ("Convert", self.convert),
+            ("Consume", self.consume),
+            ("GetConverted", self.get_converted),
+            ("Eradicate", self.eradicate),
+        ]
+    }
+}
+
+impl AddAssign for OutcomeCounts {
+    fn add_assign(&mut self, rhs: Self) {
+        self.evade += rhs.evade;
+        self.swap_and_move += rhs.swap_and_move;
+        self.swap_and_stop += rhs.swap_and_stop;
+        self.convert += rhs.convert;
+        self.consume += rhs.consume;
+        self.get_converted += rhs.get_converted;
+        self.eradicate += rhs.eradicate;
+    }
+}
@@ -1,13 +1,15 @@
 use crate::entities::direction::Direction;
-use crate::entities::direction::Direction::{
-    Down, Left, LeftDown, LeftUp, Right, RightDown, RightUp, Up,
-};
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 
 #[derive(Debug)]
 pub struct Universe<T> {
     pub area: Vec<T>,
     pub width: usize,
     pub height: usize,
+    /// What happens at the edges of the universe, see [Universe::boundaries].
+    pub(crate) boundaries: Boundaries,
 }
 
 impl<T: Default> Universe<T> {
@@ -17,6 +19,7 @@ impl<T: Default> Universe<T> {
             area: Self::gen_area(width, height),
             width,
             height,
+            boundaries: Boundaries::default(),
         }
     }
 
@@ -31,58 +34,221 @@ impl<T: Default> Universe<T> {
         vec
     }
 
+    /// Returns whether the given position is inside of the universe.
+    pub fn contains(&self, pos: &Position) -> bool {
+        pos.x < self.width && pos.y < self.height
+    }
+
     /// Returns a cell at the given position, or [None] if position is outside of area.
     pub fn get_cell(&self, pos: &Position) -> Option<&T> {
+        if !self.contains(pos) {
+            return None;
+        }
+
         self.area.get(self.pos_to_i(pos))
     }
 
-    /// Returns a valid neighbor and its position from a given position based on [Direction],
-    /// or [None] if neighbor would be outside of area.
-    pub(crate) fn get_neighbor(&self, pos: &Position, dir: &Direction) -> Option<(Position, &T)> {
-        let neighbor_pos = self.get_neighbor_pos(pos, dir)?;
-        let neighbor = self.get_cell(&neighbor_pos)?;
+    /// Returns a mutable cell at the given position, or [None] if position is outside of area.
+    pub fn get_cell_mut(&mut self, pos: &Position) -> Option<&mut T> {
+        if !self.contains(pos) {
+            return None;
+        }
 
-        Some((neighbor_pos, neighbor))
+        let index = self.pos_to_i(pos);
+        self.area.get_mut(index)
     }
 
-    fn get_neighbor_pos(&self, pos: &Position, dir: &Direction) -> Option<Position> {
-        let Position { x, y } = *pos;
+    /// Returns the position of a neighbor from a given position based on [Direction],
+    /// or [None] if neighbor would be behind a [Boundary::Wall] or [Boundary::Void].
+    pub(crate) fn get_neighbor_pos(&self, pos: &Position, dir: &Direction) -> Option<Position> {
+        match self.neighbor(pos, dir) {
+            Neighbor::Cell(pos) => Some(pos),
+            Neighbor::Wall | Neighbor::Void => None,
+        }
+    }
 
-        let x = match dir {
-            Left | LeftUp | LeftDown => x.checked_sub(1)?,
-            Right | RightUp | RightDown => x.checked_add(1)?,
-            Up | Down => x,
-        };
+    /// Returns the neighbor from a given position based on [Direction], taking the [Boundaries]
+    /// into account.
+    ///
+    /// A diagonal step across a corner is stopped by a wall on either edge, and otherwise leaves
+    /// the universe if either edge is a void.
+    ///
+    /// A cell that would be its own neighbor, because it wraps around an axis of length 1, is
+    /// treated as being next to a wall, so that it is never swapped or exchanges heat with itself.
+    pub(crate) fn neighbor(&self, pos: &Position, dir: &Direction) -> Neighbor {
+        let (dx, dy) = dir.offset();
+        let Boundaries {
+            left,
+            right,
+            top,
+            bottom,
+        } = self.boundaries;
 
-        let y = match dir {
-            Up | LeftUp | RightUp => y.checked_sub(1)?,
-            Down | LeftDown | RightDown => y.checked_add(1)?,
-            Left | Right => y,
-        };
+        let x = step(pos.x, dx, self.width, left, right);
+        let y = step(pos.y, dy, self.height, top, bottom);
 
-        if x < self.width && y < self.height {
-            Some(Position { x, y })
-        } else {
-            None
+        match (x, y) {
+            (Err(Boundary::Wall), _) | (_, Err(Boundary::Wall)) => Neighbor::Wall,
+            (Err(_), _) | (_, Err(_)) => Neighbor::Void,
+            (Ok(x), Ok(y)) if x == pos.x && y == pos.y => Neighbor::Wall,
+            (Ok(x), Ok(y)) => Neighbor::Cell(Position { x, y }),
         }
     }
 
+    /// Returns the [Boundaries] of the universe.
+    pub fn boundaries(&self) -> &Boundaries {
+        &self.boundaries
+    }
+
     /// Converts an index of of the internal area to [Position].
-    pub(crate) fn i_to_pos(&self, index: usize) -> Position {
+    pub fn i_to_pos(&self, index: usize) -> Position {
         let x = index % self.width;
         let y = (index - x) / self.width;
 
         Position { x, y }
     }
 
-    /// Converts a [Position] to an index of the internal area.
+    /// Converts a [Position] to an index of the internal area. The position has to be inside of
+    /// the universe, as positions to the right of it would end up in the next row.
     pub(crate) fn pos_to_i(&self, position: &Position) -> usize {
+        debug_assert!(self.contains(position), "{position:?} is out of bounds");
         let Position { x, y } = position;
 
         y * self.width + x
     }
 }
 
+/// Moves a coordinate by -1, 0 or 1 within `0..len`. Returns the [Boundary] that is crossed
+/// instead, unless it wraps around.
+fn step(
+    coordinate: usize,
+    delta: isize,
+    len: usize,
+    low: Boundary,
+    high: Boundary,
+) -> Result<usize, Boundary> {
+    match delta {
+        -1 if coordinate == 0 => match low {
+            Boundary::Wrap => Ok(len - 1),
+            boundary => Err(boundary),
+        },
+        1 if coordinate + 1 >= len => match high {
+            Boundary::Wrap => Ok(0),
+            boundary => Err(boundary),
+        },
+        _ => Ok(coordinate.wrapping_add_signed(delta)),
+    }
+}
+
+/// The neighbor of a cell, see [Universe::neighbor].
+#[derive(Debug)]
+pub(crate) enum Neighbor {
+    Cell(Position),
+    /// The neighbor would be behind a [Boundary::Wall].
+    Wall,
+    /// The neighbor would be behind a [Boundary::Void].
+    Void,
+}
+
+/// What happens to particles at an edge of a [Universe].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Boundary {
+    /// The edge is solid, so particles are stopped by it.
+    #[default]
+    Wall,
+    /// Particles that cross the edge enter the universe again at the opposite edge, which has to
+    /// wrap around as well.
+    Wrap,
+    /// Particles that cross the edge are deleted, see [Simulation::voided].
+    ///
+    /// [Simulation::voided]: crate::sand_sim::Simulation::voided
+    Void,
+}
+
+/// The [Boundary] of each edge of a [Universe], which are all walls by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Boundaries {
+    pub left: Boundary,
+    pub right: Boundary,
+    pub top: Boundary,
+    pub bottom: Boundary,
+}
+
+impl Boundaries {
+    /// Returns boundaries that are the same for all edges.
+    pub fn all(boundary: Boundary) -> Self {
+        Self {
+            left: boundary,
+            right: boundary,
+            top: boundary,
+            bottom: boundary,
+        }
+    }
+
+    /// Returns whether the left and right edges wrap around.
+    pub fn wraps_x(&self) -> bool {
+        self.left == Boundary::Wrap
+    }
+
+    /// Returns whether the top and bottom edges wrap around.
+    pub fn wraps_y(&self) -> bool {
+        self.top == Boundary::Wrap
+    }
+
+    /// Checks that edges only wrap around together with their opposite edge.
+    pub fn validate(&self) -> Result<(), BoundaryError> {
+        let wraps = |boundary| boundary == Boundary::Wrap;
+        if wraps(self.left) != wraps(self.right) || wraps(self.top) != wraps(self.bottom) {
+            return Err(BoundaryError);
+        }
+
+        Ok(())
+    }
+}
+
+/// The error of [Boundaries] where only one of two opposite edges wraps around.
+#[derive(Debug)]
+pub struct BoundaryError;
+
+impl Display for BoundaryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "edges can only wrap around together with their opposite edge"
+        )
+    }
+}
+
+impl Error for BoundaryError {}
+
+/// The part of a [Universe] that stays in place when it is resized, see [Simulation::resize].
+///
+/// [Simulation::resize]: crate::sand_sim::Simulation::resize
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    /// Keeps the top left corner in place, so the universe grows or shrinks at the right and the
+    /// bottom.
+    TopLeft,
+    /// Keeps the center in place, so the universe grows or shrinks evenly at all edges.
+    Center,
+    /// Keeps the center of the bottom edge in place, so the ground stays where it is.
+    Bottom,
+}
+
+impl Anchor {
+    /// Returns the offset of the cells of a universe of the old size in one of the new size.
+    pub(crate) fn offset(&self, old: (usize, usize), new: (usize, usize)) -> (isize, isize) {
+        let dx = new.0 as isize - old.0 as isize;
+        let dy = new.1 as isize - old.1 as isize;
+
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Center => (dx / 2, dy / 2),
+            Anchor::Bottom => (dx / 2, dy),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Position {
     pub x: usize,
@@ -94,3 +260,109 @@ impl Position {
         Self { x, y }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universe(width: usize, height: usize, boundaries: Boundaries) -> Universe<u8> {
+        let mut universe = Universe::new(width, height);
+        universe.boundaries = boundaries;
+        universe
+    }
+
+    #[test]
+    fn walls_stop_neighbors() {
+        let universe = universe(3, 3, Boundaries::all(Boundary::Wall));
+
+        let corner = Position { x: 0, y: 0 };
+        assert!(matches!(
+            universe.neighbor(&corner, &Direction::Left),
+            Neighbor::Wall
+        ));
+        assert!(matches!(
+            universe.neighbor(&corner, &Direction::LeftDown),
+            Neighbor::Wall
+        ));
+        assert!(matches!(
+            universe.neighbor(&corner, &Direction::RightDown),
+            Neighbor::Cell(Position { x: 1, y: 1 })
+        ));
+    }
+
+    #[test]
+    fn wrapping_edges_lead_to_the_opposite_edge() {
+        let universe = universe(4, 3, Boundaries::all(Boundary::Wrap));
+
+        let corner = Position { x: 0, y: 0 };
+        assert!(matches!(
+            universe.neighbor(&corner, &Direction::LeftUp),
+            Neighbor::Cell(Position { x: 3, y: 2 })
+        ));
+        let corner = Position { x: 3, y: 2 };
+        assert!(matches!(
+            universe.neighbor(&corner, &Direction::RightDown),
+            Neighbor::Cell(Position { x: 0, y: 0 })
+        ));
+    }
+
+    #[test]
+    fn cells_are_never_their_own_neighbor() {
+        let universe = universe(1, 3, Boundaries::all(Boundary::Wrap));
+
+        let pos = Position { x: 0, y: 1 };
+        assert!(matches!(
+            universe.neighbor(&pos, &Direction::Left),
+            Neighbor::Wall
+        ));
+        assert!(matches!(
+            universe.neighbor(&pos, &Direction::Right),
+            Neighbor::Wall
+        ));
+        assert!(matches!(
+            universe.neighbor(&pos, &Direction::RightDown),
+            Neighbor::Cell(Position { x: 0, y: 2 })
+        ));
+    }
+
+    #[test]
+    fn anchors_keep_their_point_in_place() {
+        let (small, large) = ((4, 4), (8, 6));
+
+        assert_eq!(Anchor::TopLeft.offset(small, large), (0, 0));
+        assert_eq!(Anchor::TopLeft.offset(large, small), (0, 0));
+        assert_eq!(Anchor::Center.offset(small, large), (2, 1));
+        assert_eq!(Anchor::Center.offset(large, small), (-2, -1));
+        assert_eq!(Anchor::Bottom.offset(small, large), (2, 2));
+        assert_eq!(Anchor::Bottom.offset(large, small), (-2, -2));
+    }
+
+    #[test]
+    fn voids_win_over_wrapping_in_corners() {
+        let boundaries = Boundaries {
+            left: Boundary::Wrap,
+            right: Boundary::Wrap,
+            top: Boundary::Wall,
+            bottom: Boundary::Void,
+        };
+        let universe = universe(3, 3, boundaries);
+
+        let pos = Position { x: 0, y: 2 };
+        assert!(matches!(
+            universe.neighbor(&pos, &Direction::Down),
+            Neighbor::Void
+        ));
+        assert!(matches!(
+            universe.neighbor(&pos, &Direction::LeftDown),
+            Neighbor::Void
+        ));
+        assert!(matches!(
+            universe.neighbor(&pos, &Direction::LeftUp),
+            Neighbor::Cell(_)
+        ));
+        assert!(matches!(
+            universe.neighbor(&Position { x: 0, y: 0 }, &Direction::LeftUp),
+            Neighbor::Wall
+        ));
+    }
+}
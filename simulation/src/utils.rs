@@ -1,17 +1,10 @@
-use rand::{random, thread_rng, Rng};
+use rand_chacha::ChaCha8Rng;
 
-pub fn rand_select<T>(a: T, b: T) -> T {
-    if random() {
-        a
-    } else {
-        b
-    }
-}
-
-pub fn rand_select3<T>(a: T, b: T, c: T) -> T {
-    match thread_rng().gen_range(0..3) {
-        0 => a,
-        1 => b,
-        _ => c,
-    }
-}
+/// The random number generator used by the simulation. It is seedable, so that a [Simulation]
+/// can be reproduced.
+///
+/// The algorithm is fixed, unlike the one of [rand::rngs::StdRng], so that replays and seeded
+/// tests stay reproducible across versions of `rand`.
+///
+/// [Simulation]: crate::sand_sim::Simulation
+pub type SimRng = ChaCha8Rng;
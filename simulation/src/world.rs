@@ -0,0 +1,746 @@
+//! An unbounded world made of chunks, which are created when particles or edits reach them, and
+//! stored on disk while they are idle.
+//!
+//! Every tick, each row of consecutive awake chunks of a [World] is put together with its
+//! neighbors into a [Universe], which is advanced by a [Simulation]. The neighbors are not
+//! simulated along with the row, but give the particles of the awake chunks room to move into,
+//! and exchange heat with them across all edges of the row. A chunk is wider than the reach of a
+//! particle within a tick, so no particle ever reaches the edge of such a universe.
+//!
+//! The rows are advanced one after another, from the bottom to the top, and share the cells of
+//! the chunks they overlap in. Particles are marked as unhandled once per tick for all of them, so
+//! that a particle that moved into the next row is not simulated again.
+
+use crate::chunks::{Region, CHUNK_SIZE, MAX_VELOCITY, SLEEP_DELAY};
+use crate::entities::material::Material;
+use crate::entities::registry::MaterialRegistry;
+use crate::heat;
+use crate::sand_sim::{Cell, SimError, Simulation};
+use crate::snapshot::{self, SnapshotError};
+use crate::universe::{Boundaries, Position, Universe};
+use rand::random;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// The edge length of the chunks of a [World].
+pub const WORLD_CHUNK_SIZE: usize = 64;
+
+// the chunks of the simulation must line up with the ones of the world, and a particle must not be
+// able to cross a chunk within a tick, even if it moves twice because it was swapped into a chunk
+// that is simulated later
+#[allow(clippy::manual_is_multiple_of)] // is_multiple_of needs Rust 1.87
+const _: () = assert!(WORLD_CHUNK_SIZE % CHUNK_SIZE == 0);
+const _: () = assert!(WORLD_CHUNK_SIZE > 2 * (MAX_VELOCITY as usize + 1));
+
+/// The number of ticks a chunk stays in memory after it came to rest, unless changed otherwise.
+pub const DEFAULT_UNLOAD_DELAY: u64 = 256;
+
+/// The position of a cell in a [World], which may be negative.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WorldPosition {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl WorldPosition {
+    pub fn new(x: i64, y: i64) -> Self {
+        Self { x, y }
+    }
+
+    /// Returns the position of the chunk that contains this position.
+    pub fn chunk(&self) -> ChunkPosition {
+        let size = WORLD_CHUNK_SIZE as i64;
+
+        ChunkPosition::new(self.x.div_euclid(size), self.y.div_euclid(size))
+    }
+
+    /// Returns the position within its chunk.
+    pub fn within_chunk(&self) -> Position {
+        let size = WORLD_CHUNK_SIZE as i64;
+
+        Position::new(
+            self.x.rem_euclid(size) as usize,
+            self.y.rem_euclid(size) as usize,
+        )
+    }
+}
+
+/// The position of a chunk of a [World], in chunks. Chunks are ordered row by row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkPosition {
+    pub y: i64,
+    pub x: i64,
+}
+
+impl ChunkPosition {
+    pub fn new(x: i64, y: i64) -> Self {
+        Self { y, x }
+    }
+
+    /// Returns the position of the top left cell of the chunk.
+    pub fn origin(&self) -> WorldPosition {
+        let size = WORLD_CHUNK_SIZE as i64;
+
+        WorldPosition::new(self.x * size, self.y * size)
+    }
+
+    /// Returns the position of the chunk itself and of its eight neighbors.
+    fn with_neighbors(self) -> impl Iterator<Item = ChunkPosition> {
+        (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| Self::new(self.x + dx, self.y + dy)))
+    }
+}
+
+/// A chunk of a [World] that is in memory.
+struct WorldChunk {
+    /// The cells of the chunk, row by row.
+    cells: Vec<Cell>,
+    /// The number of consecutive ticks the chunk has been at rest for.
+    idle_ticks: u64,
+    /// Whether the chunk changed since it was last stored.
+    dirty: bool,
+}
+
+impl WorldChunk {
+    fn is_awake(&self) -> bool {
+        self.idle_ticks < u64::from(SLEEP_DELAY)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.cells.iter().all(|cell| cell.material == Material::AIR)
+    }
+}
+
+/// An unbounded world, which keeps the chunks that are in use in memory and stores the others in
+/// a directory. See the [module docs](self).
+pub struct World {
+    sim: Simulation,
+    chunks: BTreeMap<ChunkPosition, WorldChunk>,
+    /// The chunks that have a file in the directory, so that ticks can tell whether a chunk has
+    /// to be read or created without asking the file system.
+    stored: BTreeSet<ChunkPosition>,
+    directory: PathBuf,
+    unload_delay: u64,
+    /// The number of ticks simulated since the world was opened.
+    ticks: u64,
+}
+
+impl World {
+    /// Opens the world of the built-in materials that is stored in the given directory, which is
+    /// created if it does not exist yet. The world gets a random seed.
+    pub fn open(directory: impl Into<PathBuf>) -> io::Result<Self> {
+        Self::open_with_materials(directory, random(), MaterialRegistry::builtin())
+    }
+
+    /// Opens the world of the materials in the given [MaterialRegistry] that is stored in the given
+    /// directory, whose randomness is derived from the given seed.
+    pub fn open_with_materials(
+        directory: impl Into<PathBuf>,
+        seed: u64,
+        materials: MaterialRegistry,
+    ) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        let mut stored = BTreeSet::new();
+        for entry in fs::read_dir(&directory)? {
+            if let Some(pos) = entry?.file_name().to_str().and_then(parse_chunk_file) {
+                stored.insert(pos);
+            }
+        }
+
+        Ok(Self {
+            sim: Simulation::with_materials(0, 0, seed, materials),
+            chunks: BTreeMap::new(),
+            stored,
+            directory,
+            unload_delay: DEFAULT_UNLOAD_DELAY,
+            ticks: 0,
+        })
+    }
+
+    /// Returns the [MaterialRegistry] of the world.
+    pub fn materials(&self) -> &MaterialRegistry {
+        self.sim.materials()
+    }
+
+    /// Sets the number of ticks after which chunks at rest are stored on disk and removed from
+    /// memory. Defaults to [DEFAULT_UNLOAD_DELAY].
+    pub fn set_unload_delay(&mut self, ticks: u64) {
+        self.unload_delay = ticks;
+    }
+
+    /// Returns the number of ticks simulated since the world was opened.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Returns the positions of all chunks that are in memory.
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = ChunkPosition> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Returns the cell at the given position, or [None] if its chunk is not in memory.
+    pub fn get_cell(&self, pos: &WorldPosition) -> Option<&Cell> {
+        let chunk = self.chunks.get(&pos.chunk())?;
+        let Position { x, y } = pos.within_chunk();
+
+        chunk.cells.get(y * WORLD_CHUNK_SIZE + x)
+    }
+
+    /// Loads the chunk at the given position into memory, unless it already is.
+    pub fn load(&mut self, pos: ChunkPosition) -> Result<(), SnapshotError> {
+        self.chunk_mut(pos)?;
+        Ok(())
+    }
+
+    /// Replaces the cell at the given position with a new particle of a [Material], loading or
+    /// creating its chunk. Wakes up the chunk and its neighbors that are in memory.
+    ///
+    /// Fails with a [SimError::UnknownMaterial] if the material is not registered with the world.
+    pub fn paint(&mut self, pos: &WorldPosition, material: Material) -> Result<(), WorldError> {
+        self.sim.check_material(material)?;
+        let particle = self.sim.materials().particle(material);
+        let Position { x, y } = pos.within_chunk();
+
+        let chunk = self.chunk_mut(pos.chunk())?;
+        chunk.cells[y * WORLD_CHUNK_SIZE + x] = particle;
+        chunk.dirty = true;
+
+        for neighbor in pos.chunk().with_neighbors() {
+            if let Some(chunk) = self.chunks.get_mut(&neighbor) {
+                chunk.idle_ticks = 0;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Advances the world by one step, see [Simulation::tick].
+    pub fn tick(&mut self) -> Result<(), SnapshotError> {
+        self.step(false)
+    }
+
+    /// Advances the world by one step. Uses multithreading where possible.
+    pub fn par_tick(&mut self) -> Result<(), SnapshotError> {
+        self.step(true)
+    }
+
+    /// Stores all chunks in memory that changed since they were last stored, e.g. before the world
+    /// is closed.
+    pub fn save(&mut self) -> Result<(), SnapshotError> {
+        let dirty: Vec<_> = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.dirty)
+            .map(|(pos, _)| *pos)
+            .collect();
+
+        for pos in dirty {
+            self.store(pos)?;
+            if let Some(chunk) = self.chunks.get_mut(&pos) {
+                chunk.dirty = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn step(&mut self, parallel: bool) -> Result<(), SnapshotError> {
+        self.ticks += 1;
+
+        let awake: BTreeSet<_> = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.is_awake())
+            .map(|(pos, _)| *pos)
+            .collect();
+        let area: BTreeSet<_> = awake.iter().flat_map(|pos| pos.with_neighbors()).collect();
+
+        // chunks that are created for the tick are only kept if particles reach them
+        let mut created = BTreeSet::new();
+        for pos in &area {
+            if !self.chunks.contains_key(pos) && !self.stored.contains(pos) {
+                created.insert(*pos);
+            }
+            for cell in &mut self.chunk_mut(*pos)?.cells {
+                cell.handled = false;
+            }
+        }
+
+        let mut changed = BTreeSet::new();
+        for row in rows(&awake).into_iter().rev() {
+            changed.extend(self.tick_row(&row, &awake, parallel));
+        }
+
+        for (pos, chunk) in &mut self.chunks {
+            if changed.contains(pos) {
+                chunk.idle_ticks = 0;
+                chunk.dirty = true;
+            } else {
+                chunk.idle_ticks = chunk.idle_ticks.saturating_add(1);
+            }
+        }
+        for pos in created {
+            if self.chunks[&pos].is_empty() {
+                self.chunks.remove(&pos);
+            }
+        }
+
+        self.unload_idle()
+    }
+
+    /// Simulates a row of consecutive awake chunks, together with its neighbors, which have to be
+    /// in memory. Returns the chunks that changed.
+    fn tick_row(
+        &mut self,
+        row: &Range<ChunkPosition>,
+        awake: &BTreeSet<ChunkPosition>,
+        parallel: bool,
+    ) -> Vec<ChunkPosition> {
+        let (min_x, min_y) = (row.start.x - 1, row.start.y - 1);
+        let columns = (row.end.x - row.start.x + 2) as usize;
+        let chunks: Vec<_> = (min_y..min_y + 3)
+            .flat_map(|y| (min_x..min_x + columns as i64).map(move |x| ChunkPosition::new(x, y)))
+            .collect();
+
+        // the offset of a chunk within the universe of the row, in cells
+        let offset = |pos: &ChunkPosition| {
+            (
+                (pos.x - min_x) as usize * WORLD_CHUNK_SIZE,
+                (pos.y - min_y) as usize * WORLD_CHUNK_SIZE,
+            )
+        };
+        let width = columns * WORLD_CHUNK_SIZE;
+        let height = 3 * WORLD_CHUNK_SIZE;
+
+        let mut universe = Universe {
+            area: vec![self.sim.materials().particle(Material::AIR); width * height],
+            width,
+            height,
+            boundaries: Boundaries::default(),
+        };
+        for pos in &chunks {
+            let (x, y) = offset(pos);
+            let chunk = &self.chunks[pos];
+            for (row, cells) in chunk.cells.chunks_exact(WORLD_CHUNK_SIZE).enumerate() {
+                let start = (y + row) * width + x;
+                universe.area[start..start + WORLD_CHUNK_SIZE].clone_from_slice(cells);
+            }
+        }
+
+        let is_awake = |region: &Region| {
+            region.y.start / WORLD_CHUNK_SIZE == 1
+                && (1..columns - 1).contains(&(region.x.start / WORLD_CHUNK_SIZE))
+        };
+        let (mut universe, sim_chunks) = self.sim.tick_area(universe, is_awake, parallel);
+
+        // the simulation only conducts heat from the awake chunks to their right and bottom
+        // neighbors, so the row exchanges heat with its left neighbor and the neighbors above it
+        // here, unless they are awake and conduct heat into the row themselves
+        let size = WORLD_CHUNK_SIZE;
+        let left = (size..2 * size).map(|y| (Position::new(size - 1, y), Position::new(size, y)));
+        let top = (row.start.x..row.end.x)
+            .filter(|&x| !awake.contains(&ChunkPosition::new(x, row.start.y - 1)))
+            .flat_map(|x| {
+                let (start, _) = offset(&ChunkPosition::new(x, row.start.y));
+                (start..start + size).map(|x| (Position::new(x, size - 1), Position::new(x, size)))
+            });
+        let mut border_changed = BTreeSet::new();
+        heat::conduct_between(
+            &mut universe,
+            self.sim.materials(),
+            left.chain(top),
+            |pos| {
+                let chunk = ChunkPosition::new(
+                    min_x + (pos.x / size) as i64,
+                    min_y + (pos.y / size) as i64,
+                );
+                border_changed.insert(chunk);
+            },
+        );
+
+        let mut changed = Vec::new();
+        for pos in chunks {
+            let (x, y) = offset(&pos);
+            let region = Region {
+                x: x..x + WORLD_CHUNK_SIZE,
+                y: y..y + WORLD_CHUNK_SIZE,
+            };
+            if sim_chunks.has_changed(&region) || border_changed.contains(&pos) {
+                changed.push(pos);
+            }
+
+            let chunk = self.chunks.get_mut(&pos).expect("chunk is in memory");
+            for (row, cells) in chunk.cells.chunks_exact_mut(WORLD_CHUNK_SIZE).enumerate() {
+                let start = (y + row) * width + x;
+                cells.clone_from_slice(&universe.area[start..start + WORLD_CHUNK_SIZE]);
+            }
+        }
+
+        changed
+    }
+
+    /// Stores the chunks that have been at rest for long enough and removes them from memory.
+    fn unload_idle(&mut self) -> Result<(), SnapshotError> {
+        let idle: Vec<_> = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.idle_ticks >= self.unload_delay)
+            .map(|(pos, _)| *pos)
+            .collect();
+
+        for pos in idle {
+            if self.chunks[&pos].dirty {
+                self.store(pos)?;
+            }
+            self.chunks.remove(&pos);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the chunk at the given position, which is loaded from disk, or created if it has
+    /// never been stored.
+    fn chunk_mut(&mut self, pos: ChunkPosition) -> Result<&mut WorldChunk, SnapshotError> {
+        if !self.chunks.contains_key(&pos) {
+            let chunk = self.read(pos)?;
+            self.chunks.insert(pos, chunk);
+        }
+
+        Ok(self.chunks.get_mut(&pos).expect("chunk was just inserted"))
+    }
+
+    fn read(&self, pos: ChunkPosition) -> Result<WorldChunk, SnapshotError> {
+        if !self.stored.contains(&pos) {
+            let air = self.sim.materials().particle(Material::AIR);
+            return Ok(WorldChunk {
+                cells: vec![air; WORLD_CHUNK_SIZE * WORLD_CHUNK_SIZE],
+                idle_ticks: 0,
+                dirty: false,
+            });
+        }
+
+        let file = File::open(self.chunk_file(pos))?;
+        let universe = snapshot::read(&mut BufReader::new(file), self.sim.materials())?;
+        if universe.width != WORLD_CHUNK_SIZE || universe.height != WORLD_CHUNK_SIZE {
+            return Err(SnapshotError::InvalidDimensions);
+        }
+
+        Ok(WorldChunk {
+            cells: universe.area,
+            idle_ticks: 0,
+            dirty: false,
+        })
+    }
+
+    /// Writes a chunk to disk, or removes its file if it is empty.
+    fn store(&mut self, pos: ChunkPosition) -> Result<(), SnapshotError> {
+        let chunk = &self.chunks[&pos];
+        let path = self.chunk_file(pos);
+
+        if chunk.is_empty() {
+            if self.stored.remove(&pos) {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+
+        let universe = Universe {
+            area: chunk.cells.clone(),
+            width: WORLD_CHUNK_SIZE,
+            height: WORLD_CHUNK_SIZE,
+            boundaries: Boundaries::default(),
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        // a partly written file is still read, and fails with a proper error
+        self.stored.insert(pos);
+        snapshot::write(&mut writer, &universe, self.sim.materials())?;
+        writer.into_inner().map_err(|e| e.into_error())?;
+
+        Ok(())
+    }
+
+    fn chunk_file(&self, pos: ChunkPosition) -> PathBuf {
+        self.directory
+            .join(format!("chunk_{}_{}.snap", pos.x, pos.y))
+    }
+}
+
+/// Returns the position of the chunk that is stored in the file of the given name, or [None] if
+/// it is no chunk file.
+fn parse_chunk_file(name: &str) -> Option<ChunkPosition> {
+    let (x, y) = name
+        .strip_prefix("chunk_")?
+        .strip_suffix(".snap")?
+        .split_once('_')?;
+
+    Some(ChunkPosition::new(x.parse().ok()?, y.parse().ok()?))
+}
+
+/// Splits a set of chunks into rows of consecutive chunks, ordered from the top left to the
+/// bottom right. Each row ranges from its first chunk to the one after its last chunk.
+fn rows(chunks: &BTreeSet<ChunkPosition>) -> Vec<Range<ChunkPosition>> {
+    let mut rows: Vec<Range<ChunkPosition>> = Vec::new();
+
+    for pos in chunks {
+        match rows.last_mut() {
+            Some(row) if row.end == *pos => row.end.x += 1,
+            _ => rows.push(*pos..ChunkPosition::new(pos.x + 1, pos.y)),
+        }
+    }
+
+    rows
+}
+
+/// An error that occurred while editing a [World].
+#[derive(Debug)]
+pub enum WorldError {
+    /// The edit itself is invalid.
+    Sim(SimError),
+    /// A chunk could not be loaded from disk.
+    Snapshot(SnapshotError),
+}
+
+impl Display for WorldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sim(e) => write!(f, "invalid edit: {e}"),
+            Self::Snapshot(e) => write!(f, "failed to load chunk: {e}"),
+        }
+    }
+}
+
+impl Error for WorldError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Sim(e) => Some(e),
+            Self::Snapshot(e) => Some(e),
+        }
+    }
+}
+
+impl From<SimError> for WorldError {
+    fn from(e: SimError) -> Self {
+        Self::Sim(e)
+    }
+}
+
+impl From<SnapshotError> for WorldError {
+    fn from(e: SnapshotError) -> Self {
+        Self::Snapshot(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heat::AMBIENT_TEMPERATURE;
+    use std::env;
+
+    /// A directory for a world that is removed again once the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("sandspiel-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn open(dir: &TempDir) -> World {
+        World::open_with_materials(&dir.0, 0, MaterialRegistry::builtin()).unwrap()
+    }
+
+    fn material(world: &World, pos: &WorldPosition) -> Option<Material> {
+        world.get_cell(pos).map(|cell| cell.material)
+    }
+
+    #[test]
+    fn chunks_survive_being_unloaded() {
+        let dir = TempDir::new("unload");
+        let mut world = open(&dir);
+        world.set_unload_delay(4);
+        let pos = WorldPosition::new(-70, 130);
+        world.paint(&pos, Material::WOOD).unwrap();
+
+        for _ in 0..u64::from(SLEEP_DELAY) + 8 {
+            world.tick().unwrap();
+        }
+        assert_eq!(world.loaded_chunks().count(), 0);
+        assert_eq!(material(&world, &pos), None);
+
+        world.load(pos.chunk()).unwrap();
+        assert_eq!(material(&world, &pos), Some(Material::WOOD));
+    }
+
+    #[test]
+    fn saved_chunks_are_found_after_reopening() {
+        let dir = TempDir::new("reopen");
+        let pos = WorldPosition::new(5, -3);
+        {
+            let mut world = open(&dir);
+            world.paint(&pos, Material::WOOD).unwrap();
+            world.save().unwrap();
+        }
+
+        let mut world = open(&dir);
+        world.load(pos.chunk()).unwrap();
+        assert_eq!(material(&world, &pos), Some(Material::WOOD));
+    }
+
+    #[test]
+    fn particles_fall_across_chunks() {
+        let dir = TempDir::new("fall");
+        let mut world = open(&dir);
+        let size = WORLD_CHUNK_SIZE as i64;
+        let sand = WorldPosition::new(10, size - 1);
+        world.paint(&sand, Material::SAND).unwrap();
+        for x in 0..20 {
+            world
+                .paint(&WorldPosition::new(x, size + 1), Material::WOOD)
+                .unwrap();
+        }
+
+        for _ in 0..4 {
+            world.tick().unwrap();
+        }
+
+        assert_eq!(material(&world, &sand), Some(Material::AIR));
+        assert_eq!(
+            material(&world, &WorldPosition::new(10, size)),
+            Some(Material::SAND)
+        );
+    }
+
+    #[test]
+    fn every_tick_is_counted_once() {
+        let dir = TempDir::new("ticks");
+        let mut world = open(&dir);
+        let size = WORLD_CHUNK_SIZE as i64;
+        // chunks on a diagonal, which are ticked one row of chunks at a time
+        for i in 0..8 {
+            let pos = WorldPosition::new(i * size + 5, i * size + 5);
+            world.paint(&pos, Material::SAND).unwrap();
+        }
+
+        for _ in 0..3 {
+            world.par_tick().unwrap();
+        }
+
+        assert_eq!(world.ticks(), 3);
+        assert_eq!(world.sim.ticks(), 0);
+    }
+
+    /// A solid heat source and a solid conductor.
+    const LAVA_AND_STONE: &str = r#"
+[[material]]
+name = "Lava"
+color = [0xff, 0x40, 0x00, 0xff]
+heat = { initial_temperature = 500.0, conductivity = 1.0, source = true }
+
+[[material]]
+name = "Stone"
+color = [0x80, 0x80, 0x80, 0xff]
+heat = { conductivity = 1.0 }
+"#;
+
+    #[test]
+    fn heat_is_conducted_across_every_edge_of_an_awake_chunk() {
+        let dir = TempDir::new("heat");
+        let mut materials = MaterialRegistry::builtin();
+        materials.extend_from_toml(LAVA_AND_STONE).unwrap();
+        let (lava, stone) = (
+            materials.find("Lava").unwrap(),
+            materials.find("Stone").unwrap(),
+        );
+        let mut world = World::open_with_materials(&dir.0, 0, materials).unwrap();
+
+        // a heat source on each edge of the chunk at the origin, next to stone in its neighbors
+        let size = WORLD_CHUNK_SIZE as i64;
+        let stones = [
+            WorldPosition::new(-1, 10),
+            WorldPosition::new(size, 10),
+            WorldPosition::new(10, -1),
+            WorldPosition::new(10, size),
+        ];
+        let sources = [(0, 10), (size - 1, 10), (10, 0), (10, size - 1)];
+        for (pos, (x, y)) in stones.iter().zip(sources) {
+            world.paint(pos, stone).unwrap();
+            world.paint(&WorldPosition::new(x, y), lava).unwrap();
+        }
+        // only the chunk at the origin is awake
+        for (pos, chunk) in &mut world.chunks {
+            let awake = *pos == ChunkPosition::new(0, 0);
+            chunk.idle_ticks = if awake { 0 } else { u64::from(SLEEP_DELAY) };
+        }
+
+        world.tick().unwrap();
+
+        let temperatures: Vec<_> = stones
+            .iter()
+            .map(|pos| world.get_cell(pos).unwrap().temperature)
+            .collect();
+        assert!(temperatures[0] > AMBIENT_TEMPERATURE + 100.0);
+        assert!(temperatures.iter().all(|&t| t == temperatures[0]));
+    }
+
+    #[test]
+    fn painting_unknown_materials_fails() {
+        let dir = TempDir::new("unknown");
+        let mut world = open(&dir);
+        let unknown = Material::from_id(world.materials().len() as u16);
+
+        let result = world.paint(&WorldPosition::new(3, 4), unknown);
+        assert!(matches!(
+            result,
+            Err(WorldError::Sim(SimError::UnknownMaterial(material))) if material == unknown
+        ));
+        assert_eq!(world.loaded_chunks().count(), 0);
+    }
+
+    #[test]
+    fn chunk_files_are_parsed() {
+        let pos = ChunkPosition::new(-12, 3);
+        let dir = TempDir::new("parse");
+        let world = open(&dir);
+        let path = world.chunk_file(pos);
+        let name = path.file_name().unwrap().to_str().unwrap();
+
+        assert_eq!(parse_chunk_file(name), Some(pos));
+        assert_eq!(parse_chunk_file("chunk_1.snap"), None);
+        assert_eq!(parse_chunk_file("chunk_a_1.snap"), None);
+        assert_eq!(parse_chunk_file("other_1_2.snap"), None);
+    }
+
+    #[test]
+    fn rows_are_made_of_consecutive_chunks() {
+        let chunks = BTreeSet::from([
+            ChunkPosition::new(0, 0),
+            ChunkPosition::new(1, 0),
+            ChunkPosition::new(3, 0),
+            ChunkPosition::new(-1, 1),
+        ]);
+
+        assert_eq!(
+            rows(&chunks),
+            vec![
+                ChunkPosition::new(0, 0)..ChunkPosition::new(2, 0),
+                ChunkPosition::new(3, 0)..ChunkPosition::new(4, 0),
+                ChunkPosition::new(-1, 1)..ChunkPosition::new(0, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Golden tests of the behaviour of the built-in materials.
+//!
+//! Each scene is drawn with the characters of the [legend], run for a number of ticks with a fixed
+//! seed and compared to the expected grid in `tests/golden/<name>.txt`, so that every change to a
+//! collision rule shows up as a readable diff of those files.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current results as the expected grids instead, e.g.
+//! `UPDATE_GOLDEN=1 cargo test --package simulation --test golden`, and review the diff.
+
+use simulation::legend;
+use simulation::sand_sim::Simulation;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const SEED: u64 = 42;
+
+/// Frames the grids in the golden files, so that trailing air is not lost by editors.
+const FRAME: char = '#';
+
+#[test]
+fn sand_falls_and_piles_up() {
+    check(
+        "sand_falls_and_piles_up",
+        12,
+        &[
+            "  ■■■  ", //
+            "   ■   ",
+            "       ",
+            "       ",
+            "       ",
+            "       ",
+        ],
+    );
+}
+
+#[test]
+fn sand_sinks_in_water() {
+    check(
+        "sand_sinks_in_water",
+        12,
+        &[
+            " ■■■■■ ", //
+            "       ",
+            "◉◉◉◉◉◉◉",
+            "◉◉◉◉◉◉◉",
+            "◉◉◉◉◉◉◉",
+        ],
+    );
+}
+
+#[test]
+fn water_spreads_out() {
+    check(
+        "water_spreads_out",
+        15,
+        &[
+            "    ◉    ", //
+            "    ◉    ",
+            "    ◉    ",
+            "    ◉    ",
+            "  ====   ",
+            "         ",
+        ],
+    );
+}
+
+#[test]
+fn oil_floats_on_water() {
+    check(
+        "oil_floats_on_water",
+        15,
+        &[
+            "◉◉◉◉◉", //
+            "◉◉◉◉◉",
+            "ooooo",
+            "ooooo",
+        ],
+    );
+}
+
+#[test]
+fn fire_burns_wood() {
+    check(
+        "fire_burns_wood",
+        20,
+        &[
+            "       ", //
+            "       ", //
+            "       ", //
+            "   f   ", //
+            "=======", //
+            "=======", //
+        ],
+    );
+}
+
+#[test]
+fn fire_meets_water() {
+    check(
+        "fire_meets_water",
+        10,
+        &[
+            "  ◉◉◉  ", //
+            "       ",
+            "       ",
+            " fffff ",
+        ],
+    );
+}
+
+#[test]
+fn smoke_and_vapor_rise() {
+    check(
+        "smoke_and_vapor_rise",
+        10,
+        &[
+            "       ", //
+            "       ", //
+            "       ", //
+            "       ", //
+            " ~~ || ", //
+        ],
+    );
+}
+
+#[test]
+fn ice_next_to_fire() {
+    check(
+        "ice_next_to_fire",
+        20,
+        &[
+            "       ", //
+            "  ***  ", //
+            " f***f ", //
+            "=======", //
+        ],
+    );
+}
+
+#[test]
+fn generators_emit_particles() {
+    check(
+        "generators_emit_particles",
+        8,
+        &[
+            " S   W ", //
+            "       ", //
+            "       ", //
+            "       ", //
+            "       ", //
+        ],
+    );
+}
+
+/// Runs a scene for a number of ticks and compares it to its golden file.
+fn check(name: &str, ticks: usize, scene: &[&str]) {
+    let mut sim = scene_simulation(scene);
+    sim.set_check_invariants(true);
+    for _ in 0..ticks {
+        sim.tick();
+    }
+
+    let violations = sim.take_violations();
+    assert!(
+        violations.is_empty(),
+        "Scene '{name}' violates invariants:\n{}",
+        violations
+            .iter()
+            .map(|violation| violation.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+
+    let universe = sim.universe();
+    let actual = format!(
+        "seed {SEED}, {ticks} ticks\n{}",
+        framed(&legend::to_string(&universe.area, universe.width))
+    );
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.txt"));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap_or_else(|e| panic!("Failed to write {path:?}: {e}"));
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!("Failed to read {path:?}: {e}, run with UPDATE_GOLDEN=1 to create it")
+    });
+    // golden files might have been checked out with Windows line endings
+    let expected = expected.replace("\r\n", "\n");
+
+    assert!(
+        expected == actual,
+        "Scene '{name}' does not match {path:?}, run with UPDATE_GOLDEN=1 to update it\n\
+        expected:\n{expected}\nactual:\n{actual}"
+    );
+}
+
+/// Sets up a simulation of a scene with one string per row.
+fn scene_simulation(scene: &[&str]) -> Simulation {
+    let width = scene.first().map_or(0, |row| row.chars().count());
+    let mut area = Vec::with_capacity(width * scene.len());
+
+    for (y, row) in scene.iter().enumerate() {
+        assert_eq!(row.chars().count(), width, "Row {y} has a different width");
+        for c in row.chars() {
+            let material =
+                legend::from_char(c).unwrap_or_else(|| panic!("Unknown material '{c}' in row {y}"));
+            area.push(material);
+        }
+    }
+
+    let mut sim = Simulation::with_seed(width, scene.len(), SEED);
+    sim.fill(&area);
+    sim
+}
+
+/// Surrounds a grid with a frame.
+fn framed(grid: &str) -> String {
+    let width = grid.lines().next().map_or(0, |line| line.chars().count());
+    let border = FRAME.to_string().repeat(width + 2);
+
+    let mut framed = format!("{border}\n");
+    for line in grid.lines() {
+        framed.push_str(&format!("{FRAME}{line}{FRAME}\n"));
+    }
+    framed.push_str(&border);
+    framed.push('\n');
+
+    framed
+}
@@ -1,24 +1,29 @@
 extern crate core;
 
 use simulation::entities::material::Material;
+use simulation::entities::registry::MaterialRegistry;
+use simulation::import::{read_png, Palette};
+use simulation::legend;
+use simulation::replay::{Player, Replay};
 use simulation::sand_sim::{Cell, Simulation};
 use simulation::universe::Universe;
-use std::io;
+use std::fs::File;
+use std::{env, io};
 
-// const S: Material = Material::Sand;
-const A: Material = Material::Air;
-// const W: Material = Material::Water;
-// const w: Material = Material::WaterGenerator;
-const D: Material = Material::Wood;
-// const V: Material = Material::Vapor;
-const F: Material = Material::Fire;
+// const S: Material = Material::SAND;
+const A: Material = Material::AIR;
+// const W: Material = Material::WATER;
+// const w: Material = Material::WATER_GENERATOR;
+const D: Material = Material::WOOD;
+// const V: Material = Material::VAPOR;
+const F: Material = Material::FIRE;
 
 fn main() {
     // let mut sim = Simulation::new(30, 15);
     //
-    // let mut fill_area = vec![Material::Air; 30];
-    // fill_area[10] = Material::SandGenerator;
-    // fill_area[20] = Material::WaterGenerator;
+    // let mut fill_area = vec![Material::AIR; 30];
+    // fill_area[10] = Material::SAND_GENERATOR;
+    // fill_area[20] = Material::WATER_GENERATOR;
 
     // let mut sim = Simulation::new(12, 30);
 
@@ -32,11 +37,29 @@ fn main() {
     //     S, A, S, S, S, A,
     // ];
 
-    let sim = Simulation::new(5, 5);
+    // a replay recorded by one of the other renderers can be given as first argument, as well as
+    // a scene drawn as PNG with the colours of the materials
+    if let Some(path) = env::args().nth(1).filter(|path| path.ends_with(".replay")) {
+        play(&path);
+        return;
+    }
+
+    if let Some(path) = env::args().nth(1) {
+        let file = File::open(&path).unwrap_or_else(|e| panic!("Failed to open {path}: {e}"));
+        let palette = Palette::from_materials(&MaterialRegistry::builtin());
+        let scene = read_png(file, &palette)
+            .unwrap_or_else(|e| panic!("Failed to import scene from {path}: {e}"));
+
+        let mut sim = Simulation::new(scene.width, scene.height);
+        sim.par_fill(&scene.area);
+        run(sim);
+    }
+
+    let mut sim = Simulation::new(5, 5);
     //
-    // let mut fill_area = vec![Material::Air; 5];
-    // fill_area[1] = Material::SandGenerator;
-    // fill_area[3] = Material::WaterGenerator;
+    // let mut fill_area = vec![Material::AIR; 5];
+    // fill_area[1] = Material::SAND_GENERATOR;
+    // fill_area[3] = Material::WATER_GENERATOR;
 
     let fill_area_2d = [
         [A, A, F, A, A],
@@ -49,22 +72,37 @@ fn main() {
     let fill_area = fill_area_2d.concat();
 
     sim.par_fill(&fill_area);
+    run(sim);
+}
+
+fn run(mut sim: Simulation) -> ! {
     let mut buf = String::new();
 
     loop {
-        draw(&sim.universe);
+        draw(sim.universe());
         let _ignored = io::stdin().read_line(&mut buf);
         // sleep(Duration::from_millis(40));
         sim.par_tick();
     }
 }
 
-pub fn get_as_string(area: &[Cell], width: usize) -> String {
-    let lines: Vec<String> = area
-        .chunks(width)
-        .map(|chunk| chunk.iter().map(content_to_char).collect::<String>())
-        .collect();
-    lines.join("\n")
+/// Replays a recorded session tick by tick.
+fn play(path: &str) {
+    let mut file = File::open(path).unwrap_or_else(|e| panic!("Failed to open {path}: {e}"));
+    let replay = Replay::read(&mut file, MaterialRegistry::builtin())
+        .unwrap_or_else(|e| panic!("Failed to read replay from {path}: {e}"));
+    let mut player =
+        Player::new(&replay).unwrap_or_else(|e| panic!("Failed to start replay {path}: {e}"));
+    let mut buf = String::new();
+
+    loop {
+        draw(player.simulation().universe());
+        println!("tick {}/{}", player.tick(), replay.ticks());
+        let _ignored = io::stdin().read_line(&mut buf);
+        if !player.step() {
+            break;
+        }
+    }
 }
 
 pub fn draw(universe: &Universe<Cell>) {
@@ -75,19 +113,5 @@ pub fn draw(universe: &Universe<Cell>) {
 
     print!("{esc}c", esc = 27 as char);
 
-    println!("{}", get_as_string(&universe.area, universe.width));
-}
-
-fn content_to_char(content: &Cell) -> char {
-    match content.lock().unwrap().material {
-        Material::Sand => '■',
-        Material::SandGenerator => 'S',
-        Material::Air => ' ',
-        Material::Water => '◉',
-        Material::WaterGenerator => 'W',
-        Material::Fire => 'f',
-        Material::Smoke => '~',
-        Material::Vapor => '|',
-        Material::Wood => '=',
-    }
+    println!("{}", legend::to_string(&universe.area, universe.width));
 }
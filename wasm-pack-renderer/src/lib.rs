@@ -1,8 +1,10 @@
 mod utils;
 
 use crate::utils::set_panic_hook;
-use simulation::entities::cell_content::Particle;
 use simulation::entities::material::Material;
+use simulation::entities::registry::MaterialRegistry;
+use simulation::history::History;
+use simulation::replay::{Operation, Recorder};
 use simulation::sand_sim::{Cell, Simulation};
 use simulation::universe::{Position, Universe};
 use wasm_bindgen::prelude::*;
@@ -14,9 +16,14 @@ use wasm_bindgen::Clamped;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// The number of edits that can be undone.
+const HISTORY_CAPACITY: usize = 64;
+
 #[wasm_bindgen]
 pub struct WasmPackRenderer {
     sim: Simulation,
+    /// Records all changes to the simulation while a session is being recorded.
+    recorder: Option<Recorder>,
 }
 
 #[wasm_bindgen]
@@ -24,78 +31,148 @@ impl WasmPackRenderer {
     pub fn new(width: usize, height: usize) -> Self {
         set_panic_hook();
 
-        let sim = Simulation::new(width, height);
-        let mut fill_area = vec![Material::Air; width * height];
-        fill_area[width / 2] = Material::SandGenerator;
+        let mut sim = Simulation::new(width, height);
+        let mut fill_area = vec![Material::AIR; width * height];
+        fill_area[width / 2] = Material::SAND_GENERATOR;
 
         // fill lower half with water
         for i in (width * (height / 2))..fill_area.len() {
-            fill_area[i] = Material::Water;
+            fill_area[i] = Material::WATER;
         }
 
         sim.fill(&fill_area);
-        Self { sim }
+        sim.set_history(History::new(HISTORY_CAPACITY, false));
+        sim.set_collect_stats(true);
+        Self {
+            sim,
+            recorder: None,
+        }
     }
 
     pub fn tick(&mut self) {
-        self.sim.tick();
+        match &mut self.recorder {
+            Some(recorder) => recorder.tick(&mut self.sim),
+            None => self.sim.tick(),
+        }
+    }
+
+    /// Finishes an edit, e.g. a brush stroke, so that it is undone at once.
+    pub fn commit_edit(&mut self) {
+        self.perform(Operation::CommitEdit);
+    }
+
+    /// Reverts the latest edit.
+    pub fn undo(&mut self) {
+        self.perform(Operation::Undo);
+    }
+
+    /// Restores the latest undone edit.
+    pub fn redo(&mut self) {
+        self.perform(Operation::Redo);
+    }
+
+    /// Starts recording the session, see [Recorder].
+    pub fn start_recording(&mut self) -> Result<(), JsError> {
+        self.recorder = Some(Recorder::start(&mut self.sim, false)?);
+        Ok(())
+    }
+
+    /// Stops recording the session and returns the replay file, or [None] if no session was
+    /// being recorded.
+    pub fn stop_recording(&mut self) -> Result<Option<Vec<u8>>, JsError> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(None);
+        };
+
+        let mut replay = Vec::new();
+        recorder.finish().write(&mut replay)?;
+        Ok(Some(replay))
+    }
+
+    /// Returns the statistics of the last tick as text, one value per line.
+    pub fn get_stats(&self) -> Option<String> {
+        let stats = self.sim.stats()?;
+        let mut lines = vec![
+            format!("Moved: {}", stats.moved),
+            format!("Voided: {}", stats.voided),
+        ];
+
+        for (material, behavior) in self.sim.materials().materials() {
+            let count = stats.count(material);
+            if count > 0 {
+                lines.push(format!("{}: {count}", behavior.name()));
+            }
+        }
+        for (outcome, count) in stats.outcomes.named() {
+            lines.push(format!("{outcome}: {count}"));
+        }
+
+        Some(lines.join("\n"))
     }
 
     pub fn get_data(&self) -> Clamped<Vec<u8>> {
-        Clamped(to_u8(&self.sim.universe))
+        Clamped(to_u8(self.sim.universe(), self.sim.materials()))
     }
 
-    /// Adds a [Material] at a specific point in the [Universe] of the [Simulation]
-    pub fn add_material(&mut self, material: &str, x: isize, y: isize) {
-        let position = Position::new(x.unsigned_abs(), y.unsigned_abs());
-        let material = match material {
-            "sand" => Material::Sand,
-            "water" => Material::Water,
-            "fire" => Material::Fire,
-            "smoke" => Material::Smoke,
-            "vapor" => Material::Vapor,
-            "wood" => Material::Wood,
-            _ => panic!("Tried to add unknown material '{material}'"),
+    /// Adds a [Material] at a specific point in the [Universe] of the [Simulation]. Fails for
+    /// unknown materials and positions outside of the universe.
+    pub fn add_material(&mut self, material: &str, x: isize, y: isize) -> Result<(), JsError> {
+        let material = self.sim.find_material(material)?;
+        let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+            return Err(JsError::new(&format!(
+                "position ({x}, {y}) is outside of the universe"
+            )));
         };
-        let content = Particle::new(material, true, 0);
-
-        self.sim
-            .universe
-            .get_cell(&position)
-            .unwrap()
-            .lock()
-            .unwrap()
-            .clone_from(&content);
+        let position = Position::new(x, y);
+        self.sim.try_get_cell(&position)?;
+
+        self.perform(Operation::Paint {
+            pos: position,
+            material,
+        });
+
+        Ok(())
+    }
+
+    /// Paints a line of a [Material] with the given thickness, e.g. a brush stroke between two
+    /// mouse positions. Parts outside of the [Universe] are clipped. Fails for unknown materials.
+    pub fn paint_line(
+        &mut self,
+        material: &str,
+        from_x: usize,
+        from_y: usize,
+        to_x: usize,
+        to_y: usize,
+        thickness: usize,
+    ) -> Result<(), JsError> {
+        let material = self.sim.find_material(material)?;
+
+        self.perform(Operation::PaintLine {
+            from: Position::new(from_x, from_y),
+            to: Position::new(to_x, to_y),
+            thickness,
+            material,
+        });
+
+        Ok(())
     }
 }
 
-const AIR_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0x00];
-const WATER_COLOR: [u8; 4] = [0, 0, 0xff, 0xff];
-const SAND_COLOR: [u8; 4] = [0xff, 0xff, 0, 0xff];
-const WATER_GENERATOR_COLOR: [u8; 4] = [0, 0xff, 0xff, 0xff];
-const SAND_GENERATOR_COLOR: [u8; 4] = [0xff, 0, 0xff, 0xff];
-const FIRE_COLOR: [u8; 4] = [0xff, 0, 0, 0xff];
-const SMOKE_COLOR: [u8; 4] = [0x0, 0x0, 0x0, 0x7f];
-const VAPOR_COLOR: [u8; 4] = [0, 0, 0xff, 0x77];
-const WOOD_COLOR: [u8; 4] = [0xDE, 0xB8, 0x87, 0xff];
-
-fn to_u8(universe: &Universe<Cell>) -> Vec<u8> {
+impl WasmPackRenderer {
+    /// Performs an [Operation] on the simulation, recording it if a session is being recorded.
+    fn perform(&mut self, operation: Operation) {
+        match &mut self.recorder {
+            Some(recorder) => recorder.apply(&mut self.sim, operation),
+            None => operation.apply(&mut self.sim),
+        }
+    }
+}
+
+fn to_u8(universe: &Universe<Cell>, materials: &MaterialRegistry) -> Vec<u8> {
     let mut out: Vec<u8> = Vec::with_capacity(universe.area.len() * 4);
 
     for cell in &universe.area {
-        let color = match cell.lock().unwrap().material {
-            Material::Sand => &SAND_COLOR,
-            Material::SandGenerator => &SAND_GENERATOR_COLOR,
-            Material::Water => &WATER_COLOR,
-            Material::WaterGenerator => &WATER_GENERATOR_COLOR,
-            Material::Air => &AIR_COLOR,
-            Material::Fire => &FIRE_COLOR,
-            Material::Smoke => &SMOKE_COLOR,
-            Material::Vapor => &VAPOR_COLOR,
-            Material::Wood => &WOOD_COLOR,
-        };
-
-        out.extend_from_slice(color);
+        out.extend_from_slice(&materials.get(cell.material).color());
 
         // out[4 * i..4 * i + 4].copy_from_slice(color);
     }
@@ -3,6 +3,8 @@ import {WasmPackRenderer} from "wasm-pack-renderer"
 let selectedMaterial = "sand"
 let mouseDown = false
 let mousePosition = {x: 0, y: 0};
+// the end of the last brush stroke, so that fast mouse movements still paint connected lines
+let lastBrushPosition = null;
 
 
 window.changeMaterial = (material) => {
@@ -11,6 +13,7 @@ window.changeMaterial = (material) => {
 }
 
 let tickInterval = document.getElementById("tick_interval");
+let brushSize = document.getElementById("brush_size");
 
 const pausedCheckbox = document.getElementById("paused-checkbox")
 
@@ -24,9 +27,18 @@ pausedCheckbox.onchange = (event) => {
 
 const canvas = document.getElementById("sandspiel-canvas");
 
+// every brush stroke is undone at once
+const endStroke = () => {
+    if (mouseDown) {
+        renderer.commit_edit();
+    }
+    mouseDown = false;
+    lastBrushPosition = null;
+};
+
 canvas.onmousedown = () => mouseDown = true;
-canvas.onmouseup = () => mouseDown = false;
-canvas.onmouseleave = () => mouseDown = false;
+canvas.onmouseup = endStroke;
+canvas.onmouseleave = endStroke;
 
 canvas.onmousemove = (event) => {
     mousePosition.x = event.clientX;
@@ -40,6 +52,52 @@ const height = 200;
 
 const renderer = WasmPackRenderer.new(width, height);
 
+const undo = () => {
+    renderer.undo();
+    draw();
+};
+
+const redo = () => {
+    renderer.redo();
+    draw();
+};
+
+document.getElementById("undo-button").onclick = undo;
+document.getElementById("redo-button").onclick = redo;
+
+const recordButton = document.getElementById("record-button");
+
+recordButton.onclick = () => {
+    const replay = renderer.stop_recording();
+    if (replay === undefined) {
+        renderer.start_recording();
+        recordButton.textContent = "Stop Recording";
+        return;
+    }
+
+    // offer the replay as download, so that it can be attached to bug reports
+    const link = document.createElement("a");
+    link.href = URL.createObjectURL(new Blob([replay]));
+    link.download = `sandspiel-${Date.now()}.replay`;
+    link.click();
+    URL.revokeObjectURL(link.href);
+    recordButton.textContent = "Record";
+};
+
+document.onkeydown = (event) => {
+    if (!event.ctrlKey && !event.metaKey) {
+        return;
+    }
+
+    if (event.key === "z") {
+        event.preventDefault();
+        undo();
+    } else if (event.key === "y") {
+        event.preventDefault();
+        redo();
+    }
+};
+
 function  getMousePos() {
     let rect = canvas.getBoundingClientRect(), // abs. size of element
         scaleX = canvas.width / rect.width,    // relationship bitmap vs. element for x
@@ -52,10 +110,13 @@ function  getMousePos() {
 }
 
 
+const stats = document.getElementById("stats");
+
 const draw = () => {
     let data = renderer.get_data();
     let imgData = new ImageData(data, width, height);
     ctx.putImageData(imgData, 0, 0);
+    stats.textContent = renderer.get_stats() || "";
 }
 
 let currentTick = 0;
@@ -75,7 +136,13 @@ const renderLoop = () => {
 
     if (mouseDown) {
         let pos = getMousePos();
-        renderer.add_material(selectedMaterial, pos.x, pos.y);
+        let from = lastBrushPosition || pos;
+        try {
+            renderer.paint_line(selectedMaterial, from.x, from.y, pos.x, pos.y, Number(brushSize.value));
+        } catch (error) {
+            console.error(error);
+        }
+        lastBrushPosition = pos;
     }
 
     draw();